@@ -5,6 +5,7 @@ use lichen::var::Var;
 use lichen::eval::Evaluator;
 
 
+#[allow(clippy::while_let_on_iterator, clippy::single_match)]
 fn main() {
     //load the lichen source file as a string
     let bytes = include_bytes!("basic.ls");
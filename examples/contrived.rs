@@ -1,12 +1,19 @@
 extern crate lichen;
 
 use std::io;
-use std::collections::HashMap;
 
 use lichen::parse::Parser;
 use lichen::var::Var;
 use lichen::eval::Evaluator;
-use lichen::source::Next;
+use lichen::step::Step;
+
+fn read_line () -> String {
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(_) => line.trim().to_owned(),
+        Err(_) => panic!()
+    }
+}
 
 fn main() {
     let bytes = include_bytes!("contrived.ls");
@@ -14,57 +21,37 @@ fn main() {
     let mut env = Parser::parse_blocks(src.to_mut()).expect("ERROR: Unable to parse source").into_env();
 
     let mut ev = Evaluator::new(&mut env);
-    
-    while let Some((vars,next)) = ev.next() {
-        for var in vars {
-            match var {
-                Var::String(s) => { println!("{:}", s); },
-                _ => {},
-            }
-        }
-        if let Some(next) = next {
-            match next {
-                Next::Await(node) => {
-                    println!("\nContinue to {:}\n", node);
-                    let mut line = String::new();
-                    
-                    match io::stdin().read_line(&mut line) {
-                        Ok(_) => {
-                            match line.trim() {
-                                "y" | "Y" => {
-                                    ev.advance(node);
-                                },
-                                _ => {}
-                            }
-                        },
-                        Err(_) => panic!()
-                    }
-                },
-                Next::Select(selects) => {
-                    println!("\nEnter in a destination");
 
-                    // we're going to invert K/V for convenience for input
-                    let mut choices: HashMap<String,String> = HashMap::new();
-                    for (key,val) in selects.iter() {
-                        println!("{:}, type {:?}", key, val[0].to_string());
-                        choices.insert(val[0].to_string(),key.clone());
-                    }
-                    
-                    let mut line = String::new();
-                    
-                    match io::stdin().read_line(&mut line) {
-                        Ok(_) => {
-                            let line = line.trim();
-                            if let Some(_) = choices.remove(line) {
-                                ev.advance(line.to_owned());
-                            }
-                        },
-                        Err(_) => panic!()
-                    }
-                },
-                _ => {},
-            }
-            
+    loop {
+        let step = ev.step().expect("ERROR: Runaway evaluation");
+        match step {
+            Step::Line { vars, .. } => {
+                for var in vars {
+                    if let Var::String(s) = var { println!("{:}", s); }
+                }
+            },
+            Step::Await { node } => {
+                println!("\nContinue to {:}\n", node);
+                match &read_line()[..] {
+                    "y" | "Y" => {
+                        if let Err(e) = ev.select(&node) { println!("{:}", e); }
+                    },
+                    _ => {}, // otherwise we continue on in the current node
+                }
+            },
+            Step::Choice { options } => {
+                println!("\nEnter in a destination");
+                for (i, option) in options.iter().enumerate() {
+                    println!("{:}, type {:}", option.key, i);
+                }
+
+                let chosen = read_line().parse::<usize>().ok()
+                    .and_then(|i| options.get(i));
+                if let Some(option) = chosen {
+                    ev.choose(&option.key).expect("ERROR: Invalid choice");
+                }
+            },
+            Step::Finished => break,
         }
     }
 }
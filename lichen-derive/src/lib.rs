@@ -0,0 +1,56 @@
+//! Derives lichen::def::LichenDef for structs with named fields
+//!
+//! Each field becomes a def entry of the same name, nested structs
+//! which also derive LichenDef become nested def blocks
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate syn;
+#[macro_use] extern crate quote;
+
+use proc_macro::TokenStream;
+use syn::{Data,DeriveInput,Fields};
+
+#[proc_macro_derive(LichenDef)]
+pub fn derive_lichen_def (input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(ts) => ts.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand (input: &DeriveInput) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let fields = match input.data {
+        Data::Struct(ref s) => {
+            match s.fields {
+                Fields::Named(ref f) => &f.named,
+                _ => return Err(syn::Error::new_spanned(input,
+                                                        "LichenDef requires named fields")),
+            }
+        },
+        _ => return Err(syn::Error::new_spanned(input, "LichenDef only supports structs")),
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let idents: Vec<_> = fields.iter().map(|f| f.ident.clone().unwrap()).collect();
+    let keys: Vec<String> = idents.iter().map(|i| i.to_string()).collect();
+
+    Ok(quote! {
+        impl #impl_generics ::lichen::def::LichenDef for #name #ty_generics #where_clause {
+            fn to_def (&self, name: &str) -> ::lichen::def::DefBlock {
+                let mut block = ::lichen::def::DefBlock::new(name);
+                #( ::lichen::def::DefField::to_field(&self.#idents, &mut block, #keys); )*
+                block
+            }
+
+            fn from_def (block: &::lichen::def::DefBlock) -> ::std::option::Option<Self> {
+                ::std::option::Option::Some(#name {
+                    #( #idents: ::lichen::def::DefField::from_field(block, #keys)?, )*
+                })
+            }
+        }
+    })
+}
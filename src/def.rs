@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{HashMap,BTreeMap};
+use std::mem;
 
 use var::Var;
 use eval::Eval;
@@ -8,129 +9,260 @@ use eval::Eval;
 pub type Def = HashMap<String, DefBlock>;
 
 #[derive(Debug,PartialEq,Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DefBlock {
     pub name: String,
-    pub data: HashMap<String,Var>
+    pub data: HashMap<String,Var>,
+    /// Nested blocks, eg: player.bag
+    pub blocks: HashMap<String,DefBlock>,
 }
 
 impl DefBlock {
     pub fn new(name: &str) -> DefBlock {
         DefBlock {
             name: name.to_owned(),
-            data: HashMap::new()
+            data: HashMap::new(),
+            blocks: HashMap::new(),
+        }
+    }
+
+    /// Builds a map of all data and nested blocks
+    pub fn to_map(&self) -> Var {
+        let mut map = BTreeMap::new();
+        for (k,v) in self.data.iter() {
+            map.insert(k.clone(), v.clone());
+        }
+        for (k,b) in self.blocks.iter() {
+            map.insert(k.clone(), b.to_map());
+        }
+
+        Var::Map(map)
+    }
+
+    /// Gets var or nested block by name
+    ///
+    /// Nested blocks are returned as a map
+    pub fn get_var(&self, lookup: &str) -> Option<Var> {
+        if let Some(v) = self.data.get(lookup) {
+            return Some(v.clone())
+        }
+
+        self.blocks.get(lookup).map(|b| b.to_map())
+    }
+}
+
+/// Finds nested block at path
+pub fn find_block<'d>(def: &'d Def, path: &[&str]) -> Option<&'d DefBlock> {
+    let (first, rest) = path.split_first()?;
+    let mut block = def.get(*first)?;
+    for name in rest {
+        block = block.blocks.get(*name)?;
+    }
+
+    Some(block)
+}
+
+/// Finds nested block at path, mutably
+pub fn find_block_mut<'d>(def: &'d mut Def, path: &[&str]) -> Option<&'d mut DefBlock> {
+    let (first, rest) = path.split_first()?;
+    let mut block = def.get_mut(*first)?;
+    for name in rest {
+        block = block.blocks.get_mut(*name)?;
+    }
+
+    Some(block)
+}
+
+/// Finds nested block at path, building any missing blocks along the way
+pub fn build_block<'d>(def: &'d mut Def, path: &[&str]) -> Option<&'d mut DefBlock> {
+    let (first, rest) = path.split_first()?;
+    let mut block = def.entry(first.to_string())
+        .or_insert_with(|| DefBlock::new(first));
+    for name in rest {
+        block = block.blocks.entry(name.to_string())
+            .or_insert_with(|| DefBlock::new(name));
+    }
+
+    Some(block)
+}
+
+/// Inserts a block at a dot-separated path, replacing any existing block
+pub fn insert_block(def: &mut Def, path: &str, mut block: DefBlock) {
+    let mut path: Vec<&str> = path.split_terminator('.').collect();
+    let name = match path.pop() {
+        Some(name) => name,
+        None => return,
+    };
+    block.name = name.to_owned();
+
+    if path.is_empty() {
+        def.insert(name.to_owned(), block);
+    }
+    else if let Some(parent) = build_block(def, &path) {
+        parent.blocks.insert(name.to_owned(), block);
+    }
+}
+
+/// Carries values over from an older block onto a freshly parsed one, see Env::reload
+///
+/// Only entries found in both, holding the same type of var, are carried over
+pub fn merge_block(block: &mut DefBlock, old: &DefBlock) {
+    for (k, v) in block.data.iter_mut() {
+        if let Some(old_v) = old.data.get(k) {
+            if mem::discriminant(v) == mem::discriminant(old_v) { *v = old_v.clone(); }
+        }
+    }
+
+    for (k, b) in block.blocks.iter_mut() {
+        if let Some(old_b) = old.blocks.get(k) { merge_block(b, old_b); }
+    }
+}
+
+/// Conversion between a Rust structure and a def block
+///
+/// Typically derived with lichen-derive, eg: #[derive(LichenDef)]
+pub trait LichenDef: Sized {
+    /// Builds a def block, with each field as an entry
+    fn to_def (&self, name: &str) -> DefBlock;
+
+    /// Rebuilds structure from def block, None if any field is missing or mistyped
+    fn from_def (block: &DefBlock) -> Option<Self>;
+}
+
+/// Field within a def block, used by LichenDef
+///
+/// Plain values are stored as vars, nested structures as nested blocks
+pub trait DefField: Sized {
+    fn to_field (&self, block: &mut DefBlock, key: &str);
+    fn from_field (block: &DefBlock, key: &str) -> Option<Self>;
+}
+
+impl DefField for Var {
+    fn to_field (&self, block: &mut DefBlock, key: &str) {
+        block.data.insert(key.to_owned(), self.clone());
+    }
+
+    fn from_field (block: &DefBlock, key: &str) -> Option<Var> {
+        block.data.get(key).cloned()
+    }
+}
+
+impl DefField for f32 {
+    fn to_field (&self, block: &mut DefBlock, key: &str) {
+        block.data.insert(key.to_owned(), Var::Num(*self));
+    }
+
+    fn from_field (block: &DefBlock, key: &str) -> Option<f32> {
+        match block.data.get(key) {
+            Some(&Var::Num(n)) => Some(n),
+            Some(&Var::Int(n)) => Some(n as f32),
+            _ => None,
+        }
+    }
+}
+
+impl DefField for i64 {
+    fn to_field (&self, block: &mut DefBlock, key: &str) {
+        block.data.insert(key.to_owned(), Var::Int(*self));
+    }
+
+    fn from_field (block: &DefBlock, key: &str) -> Option<i64> {
+        match block.data.get(key) {
+            Some(&Var::Int(n)) => Some(n),
+            Some(&Var::Num(n)) if n.fract() == 0. => Some(n as i64), // plain numbers in source
+            _ => None,
         }
     }
 }
 
+impl DefField for bool {
+    fn to_field (&self, block: &mut DefBlock, key: &str) {
+        block.data.insert(key.to_owned(), Var::Bool(*self));
+    }
+
+    fn from_field (block: &DefBlock, key: &str) -> Option<bool> {
+        match block.data.get(key) {
+            Some(&Var::Bool(b)) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+impl DefField for String {
+    fn to_field (&self, block: &mut DefBlock, key: &str) {
+        block.data.insert(key.to_owned(), Var::String(self.clone()));
+    }
+
+    fn from_field (block: &DefBlock, key: &str) -> Option<String> {
+        match block.data.get(key) {
+            Some(&Var::String(ref s)) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl<T: LichenDef> DefField for T {
+    fn to_field (&self, block: &mut DefBlock, key: &str) {
+        block.blocks.insert(key.to_owned(), self.to_def(key));
+    }
+
+    fn from_field (block: &DefBlock, key: &str) -> Option<T> {
+        block.blocks.get(key).and_then(T::from_def)
+    }
+}
+
 impl Eval for Def {
     fn get (&self, path: Option<Vec<&str>>, lookup: &str) -> Option<Var> {
         if let Some(path) = path {
-            if let Some(ref def) = self.get(path[0]) {
-                if let Some(v) = def.data.get(lookup) {
-                    return Some(v.clone())
+            // walk nested blocks as far as possible, then through any maps
+            let mut block = self.get(path[0])?;
+            let mut rest = &path[1..];
+            while let Some((name, rest_)) = rest.split_first() {
+                match block.blocks.get(*name) {
+                    Some(b) => { block = b; rest = rest_; },
+                    None => break,
                 }
             }
-        }
 
-        None
-    }
+            let (first, rest) = match rest.split_first() {
+                Some((first, rest)) => (*first, rest),
+                None => return block.get_var(lookup),
+            };
 
-    fn get_last (&self, lookup: &str) -> Option<(Var, bool)> {
-        let mut lookup = lookup;
-        let mut resolved = None;
-
-        loop { // resolve symbol references
-            let (path,sym) = self.as_path(lookup);
-            if let Some(mut path) = path {
-                // NOTE: for now we are using nested paths as actual names
-                // so we need to rebuild it as a full name if necessary
-                let mut p = String::new();
-                if path.len() > 1 {
-                    p.push_str(path.remove(0));
-                    p.push('.');
-                    p.push_str(path.remove(0));
+            let mut v = block.data.get(first)?;
+            for name in rest {
+                match v {
+                    &Var::Map(ref m) => { v = m.get(*name)?; },
+                    _ => return None,
                 }
+            }
 
-                let path_final = {
-                    if p.is_empty() { path[0] }
-                    else { &p }
-                };
-                
-                if let Some(ref def) = self.get(path_final) {
-                    if let Some(v) = def.data.get(sym) {
-                        match v {
-                            &Var::Sym(ref sym) => {
-                                if lookup != sym {
-                                    resolved = Some(v.clone()); // take note that we resolved atleast once
-                                    lookup = sym;
-                                    continue
-                                }
-                                else {
-                                    return Some((v.clone(), false))
-                                }
-                            },
-                            _ => {
-                                return Some((v.clone(), true))
-                            }
-                        }
-                    }
-                    else { break }
-                }
-                else { break }
+            match v {
+                &Var::Map(ref m) => { return m.get(lookup).cloned() },
+                _ => return None,
             }
-            else { break }
         }
 
-        if let Some(v) = resolved { return Some((v, false)) } 
-
         None
     }
 
-    #[allow(unused_variables)]
     fn set (&mut self, path: Option<Vec<&str>>, lookup: &str, var: Var) {
-        if let Some(mut path) = path {
-            // NOTE: for now we are using nested paths as actual names
-            // so we need to rebuild it as a full name if necessary
-            let mut p = String::new();
-            let mut block_name = String::new();
-            if path.len() > 1 {
-                p.push_str(path.remove(0));
-                p.push('.');
-                let n = path.remove(0);
-                block_name.push_str(&n);
-                p.push_str(n);
+        if let Some(path) = path {
+            // NOTE: we're building this from scratch if missing, this should be considered explicit instead
+            if let Some(block) = build_block(self, &path) {
+                block.data.insert(lookup.to_owned(), var);
             }
+        }
+    }
 
-            let path_final = {
-                if p.is_empty() { path[0] }
-                else { &p }
-            };
-            
-            if let Some(ref mut def) = self.get_mut(path_final) {
-                let set;
-                if let Some(v) = def.data.get_mut(lookup) {
-                    *v = var;
-                    set = None;
-                }
-                else { set = Some(var); }
-                
-                if let Some(var) = set { // NOTE: we're building this from scratch, this should be considered explicit instead
-                    def.data.insert(lookup.to_owned(), var);
-                }
-
-                return
-            }
-            
-            // if we didn't successfully insert, let's build from scratch the new block path
-            let mut map = HashMap::new();
-            map.insert(lookup.to_owned(), var);
-            
-            let def = DefBlock {
-                name: block_name,
-                data: map,
-            };
-            
-            self.insert(path_final.to_owned(), def);
+    fn clone_path (&mut self, lookup: &str, from: &str) {
+        // clone entire block when found, otherwise just the var
+        let path: Vec<&str> = from.split_terminator('.').collect();
+        match find_block(self, &path).cloned() {
+            Some(block) => insert_block(self, lookup, block),
+            None => {
+                if let Some(v) = self.get_path(from) { self.set_path(lookup, v); }
+            },
         }
     }
 
@@ -138,4 +270,8 @@ impl Eval for Def {
     fn call (&mut self, var: Var, fun: &str, vars: &Vec<Var>) -> Option<Var> {
         None
     }
+
+    fn has_block (&self, name: &str) -> bool {
+        self.contains_key(name)
+    }
 }
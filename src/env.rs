@@ -1,14 +1,19 @@
-use std::collections::HashMap;
+use std::collections::{HashMap,HashSet};
+use std::mem;
 
-use def::Def;
-use parse::{Block,SrcBlock};
+use def::{self,Def,LichenDef};
+use parse::{Parser,Block,SrcBlock,ParseError,ParseErrorKind};
+use source::Next;
 use var::Var;
 use fun::Fun;
+use lint::{self,Warning};
+use eval::Eval;
+use locale::Locale;
 
 impl Env {
     pub fn def_contains(def: &Def, path: Option<Vec<&str>>, lookup: &str) -> bool {
         if let Some(path) = path {
-            if let Some(ref def) = def.get(path[0]) {
+            if let Some(def) = def::find_block(def, &path) {
                 return def.data.contains_key(lookup)
             }
         }
@@ -17,24 +22,127 @@ impl Env {
     }
 
     pub fn empty () -> Env {
-        Env { src: HashMap::new(), def: HashMap::new(), fun: HashMap::new() }
+        Env { src: HashMap::new(), def: HashMap::new(), fun: HashMap::new(),
+              visits: HashMap::new(), locale: None }
     }
 
+    /// Inserts blocks, replacing any of the same kind and name
+    ///
+    /// See Env::try_insert to fail on duplicates instead
     pub fn insert (&mut self, mut v: Vec<Block>) {
         for b in v.drain(..) {
             match b {
                 Block::Def(db) => {
-                    self.def.insert(db.name.clone(), db);
+                    let name = db.name.clone();
+                    def::insert_block(&mut self.def, &name, db); // dotted names become nested
                 },
                 Block::Src(sb) => {
+                    self.visits.entry(sb.name.clone()).or_insert(0);
                     self.src.insert(sb.name.clone(), sb);
                 },
             }
         }
+
+        self.resolve_modules();
+    }
+
+    /// Inserts blocks, failing on a block of the same kind and fully qualified name
+    /// as one already in the environment, or another being inserted
+    ///
+    /// Dotted def names are checked at their nested path. Nothing is inserted on failure
+    pub fn try_insert (&mut self, v: Vec<Block>) -> Result<(), ParseError> {
+        let mut names = HashSet::new();
+        for b in v.iter() {
+            let exists = match b {
+                &Block::Src(ref b) => self.src.contains_key(&b.name),
+                &Block::Def(ref b) => { // dotted names are nested
+                    let path: Vec<&str> = b.name.split_terminator('.').collect();
+                    def::find_block(&self.def, &path).is_some()
+                },
+            };
+
+            if exists || !names.insert(b.key()) {
+                let name = b.name().to_owned();
+                let mut e = ParseError::new(ParseErrorKind::DuplicateBlock).token(&name[..]);
+                e.block = Some(name);
+                return Err(e)
+            }
+        }
+
+        self.insert(v);
+        Ok(())
+    }
+
+    /// Swaps in freshly parsed blocks, keeping runtime state where possible
+    ///
+    /// Unchanged nodes keep their place, visits, and locals. Changed nodes keep
+    /// their visits and locals, but start over from the top. Def entries still
+    /// found in the source keep their values, unless their type changed, while
+    /// anything else is dropped, so reinsert any Rust side defs afterwards.
+    /// See Evaluator::reload to reload while evaluating
+    pub fn reload (&mut self, parser: Parser) -> Reload {
+        let mut reload = Reload::default();
+        let mut old_src = mem::take(&mut self.src);
+        let old_def = mem::take(&mut self.def);
+        self.insert(parser.into_blocks());
+
+        for (name, b) in self.src.iter_mut() {
+            match old_src.remove(name) {
+                Some(old) => {
+                    let same = old.src == b.src && old.params == b.params &&
+                        old.line_ids == b.line_ids && old.meta == b.meta;
+                    if same { *b = old; }
+                    else {
+                        b.locals = old.locals;
+                        reload.changed.push(name.clone());
+                    }
+                },
+                None => reload.added.push(name.clone()),
+            }
+        }
+
+        for name in old_src.into_keys() {
+            self.visits.remove(&name);
+            reload.vanished.push(name);
+        }
+
+        for (name, b) in self.def.iter_mut() {
+            if let Some(old) = old_def.get(name) { def::merge_block(b, old); }
+        }
+
+        reload.changed.sort();
+        reload.vanished.sort();
+        reload.added.sort();
+        reload
+    }
+
+    /// Statically checks source blocks for dangling nodes,
+    /// undefined logic and def paths, unreachable nodes, and unused def entries
+    pub fn validate (&self) -> Vec<Warning> {
+        lint::validate(self)
+    }
+
+    /// Times a node was entered from its start
+    pub fn visits (&self, node: &str) -> usize {
+        self.visits.get(node).cloned().unwrap_or(0)
+    }
+
+    /// Inserts a Rust structure as a def block, name may be a nested dot-separated path
+    pub fn insert_def<T: LichenDef> (&mut self, name: &str, t: &T) {
+        let block = t.to_def(name);
+        def::insert_block(&mut self.def, name, block);
     }
 
+    /// Reads a def block back into a Rust structure
+    pub fn get_def<T: LichenDef> (&self, name: &str) -> Option<T> {
+        let path: Vec<&str> = name.split_terminator('.').collect();
+        def::find_block(&self.def, &path).and_then(T::from_def)
+    }
+
+    /// Inserts var into an existing def block, block may be a nested dot-separated path
     pub fn insert_var (&mut self, block: &str, name: String, var: Var) -> Option<Var> {
-        if let Some(b) = self.def.get_mut(block) {
+        let path: Vec<&str> = block.split_terminator('.').collect();
+        if let Some(b) = def::find_block_mut(&mut self.def, &path) {
             return b.data.insert(name, var)
         }
 
@@ -42,9 +150,92 @@ impl Env {
     }
 }
 
+impl<D: Eval> Env<D> {
+    /// Resolves nodes headed to from within a module, eg: next:now intro within module blacksmith
+    ///
+    /// Nodes within the same module take precedence, otherwise names are left as written
+    fn resolve_modules (&mut self) {
+        let names: HashSet<String> = self.src.keys().cloned().collect();
+        for b in self.src.values_mut() {
+            let module = match module_of(&b.name) {
+                Some(m) => m.to_owned(),
+                None => continue,
+            };
+
+            for src in b.src.iter_mut() {
+                let next = match src.next_mut() {
+                    Some(next) => next,
+                    None => continue,
+                };
+
+                for nn in next.nodes_mut() {
+                    if nn.contains("::") { continue }
+                    let qualified = format!("{}::{}", module, nn);
+                    if names.contains(&qualified) { *nn = qualified; }
+                }
+
+                if let &mut Next::Random(ref mut nodes) = next {
+                    nodes.sort_by(|a, b| a.0.cmp(&b.0));
+                }
+            }
+        }
+    }
+
+    /// Sets translations applied to emitted strings, before backtick formatting
+    ///
+    /// Strings without a translation are emitted as written
+    pub fn set_locale<L: Locale + 'static> (&mut self, locale: L) {
+        self.locale = Some(Box::new(locale));
+    }
+
+    /// Goes back to emitting strings as written
+    pub fn clear_locale (&mut self) {
+        self.locale = None;
+    }
+
+    /// Swaps out the variable store, for evaluating against Rust side state directly
+    ///
+    /// Any parsed def blocks are dropped
+    pub fn with_store<S: Eval> (self, store: S) -> Env<S> {
+        Env { def: store, src: self.src, fun: self.fun,
+              visits: self.visits, locale: self.locale }
+    }
+}
+
+/// Nodes affected by Env::reload, each sorted by name
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct Reload {
+    /// Nodes whose source changed, these start over from the top
+    pub changed: Vec<String>,
+    /// Nodes no longer found in the source
+    pub vanished: Vec<String>,
+    /// Nodes new to the source
+    pub added: Vec<String>,
+}
+
+impl Reload {
+    /// Nothing changed besides def values
+    pub fn is_empty (&self) -> bool {
+        self.changed.is_empty() && self.vanished.is_empty() && self.added.is_empty()
+    }
+}
+
+/// Module of a block name, eg: 'town::blacksmith' for 'town::blacksmith::intro'
+pub fn module_of (name: &str) -> Option<&str> {
+    name.rfind("::").map(|i| &name[..i])
+}
+
 /// Environment containing all parsed definition and source blocks
-pub struct Env {
-    pub def: Def,
+///
+/// Def blocks are the default store, see Env::with_store to use your own
+pub struct Env<D = Def> {
+    pub def: D,
     pub src: HashMap<String, SrcBlock>,
     pub fun: HashMap<String, Fun>,
+
+    /// Times each node was entered from its start, readable as 'node.visits'
+    pub visits: HashMap<String, usize>,
+
+    /// Translates emitted strings by line ID, see Env::set_locale
+    pub locale: Option<Box<dyn Locale>>,
 }
@@ -1,10 +1,17 @@
-use env::Env;
+use env::{Env,Reload,module_of};
+use parse::Parser;
 use var::Var;
-use source::{Src,Next};
-use logic::LogicFn;
-use def::DefBlock;
+use source::{Src,Next,Seq};
+use logic::{Logic,LogicFn};
+use def::Def;
+use snapshot::Snapshot;
+use rng::{self,Rng,XorShift};
+use step::{Step,Choice,Meta};
+use format::{self,Unresolved};
 
 use std::collections::HashMap;
+use std::fmt;
+use std::error::Error;
 
 /// Creates a possible path from a dot-seperated string
 ///
@@ -12,18 +19,21 @@ use std::collections::HashMap;
 /// Eg: 'items.bag.coins' becomes -> (Some(Vec['items','bag']), 'coins')
 pub fn as_path<'a> (lookup: &'a str) -> (Option<Vec<&'a str>>, &'a str) {
     let mut lookups: Vec<&'a str> = lookup.split_terminator('.').collect();
-    let item = lookups.pop().unwrap();
+    let item = match lookups.pop() {
+        Some(item) => item,
+        None => return (None, lookup), // empty lookup
+    };
 
-    let path;
-    if lookups.len() > 0 { path = Some(lookups); }
-    else { path = None }
+    let path = if !lookups.is_empty() { Some(lookups) }
+               else { None };
 
     (path,item)
 }
 
 /// Primary Evaluation trait must be implemented to run Evaluator
 ///
-/// 
+/// Def blocks implement this by default, however any Rust side store can be used
+/// in their place, see Env::with_store
 pub trait Eval {
     /// Get method to retrieve variable from Rust side
     fn get (&self, path: Option<Vec<&str>>, lookup: &str) -> Option<Var>;
@@ -38,77 +48,460 @@ pub trait Eval {
     }
 
     /// Returns final ref or var, and if it is a var
-    fn get_last (&self, lookup: &str) -> Option<(Var, bool)>;
+    ///
+    /// Symbols found are followed as references until a var is found
+    fn get_last (&self, lookup: &str) -> Option<(Var, bool)> {
+        let mut lookup = lookup.to_owned();
+        let mut resolved = None;
+
+        loop { // resolve symbol references
+            let v = {
+                let (path,sym) = self.as_path(&lookup);
+                if path.is_none() { break }
+                self.get(path, sym)
+            };
+
+            match v {
+                Some(Var::Sym(sym)) => {
+                    if lookup != sym {
+                        resolved = Some(Var::Sym(sym.clone())); // take note that we resolved atleast once
+                        lookup = sym;
+                        continue
+                    }
+                    else {
+                        return Some((Var::Sym(sym), false))
+                    }
+                },
+                Some(v) => { return Some((v, true)) },
+                None => { break },
+            }
+        }
+
+        if let Some(v) = resolved { return Some((v, false)) } 
+
+        None
+    }
     
     /// Expects var to be written to underlying mem/store in Rust
     fn set (&mut self, path: Option<Vec<&str>>, lookup: &str, var: Var);
 
     fn set_path (&mut self, lookup: &str, v: Var) {
-        let (path,lookup) = self.as_path(&lookup);
+        let (path,lookup) = self.as_path(lookup);
         self.set(path,lookup, v);
     }
 
+    /// Copies var found at one path into another, used by 'new'
+    fn clone_path (&mut self, lookup: &str, from: &str) {
+        if let Some(v) = self.get_path(from) {
+            self.set_path(lookup, v);
+        }
+    }
+
     /// A custom callable function
     ///
     /// Var represents variable to mutate
     /// Fun is the function name
     /// Vars are any additional arguments
     /// Can optionally return variable back to lichen
+    #[allow(clippy::ptr_arg)] // kept for existing implementors
     fn call (&mut self, var: Var, fun: &str, vars: &Vec<Var>) -> Option<Var>;
+
+    /// If a top level block exists, used to resolve paths relative to a module
+    fn has_block (&self, _name: &str) -> bool {
+        false
+    }
+}
+
+/// Node-local variables layered over the store
+///
+/// Plain symbols without a path resolve to locals, everything else to the store.
+/// Visit counts are read last, as 'visited:node', 'node.visits', and 'seen'
+struct Scope<'a> {
+    locals: &'a mut HashMap<String,Var>,
+    store: &'a mut dyn Eval,
+    visits: &'a HashMap<String,usize>,
+    /// Current line was emitted before
+    seen: bool,
+    /// Module of the running node, see env::module_of
+    module: Option<String>,
+}
+
+impl<'a> Scope<'a> {
+    fn visit_var (&self, path: Option<&[&str]>, lookup: &str) -> Option<Var> {
+        match path {
+            Some(path) if lookup == "visits" => {
+                self.node_visits(&path.join(".")).map(|n| Var::Int(n as i64))
+            },
+            None if lookup == "seen" => Some(Var::Bool(self.seen)),
+            None => {
+                lookup.strip_prefix("visited:")
+                    .and_then(|node| self.node_visits(node))
+                    .map(|n| Var::Bool(n > 0))
+            },
+            _ => None,
+        }
+    }
+
+    /// Visits of a node, preferring one within the same module
+    fn node_visits (&self, node: &str) -> Option<usize> {
+        if let Some(ref module) = self.module {
+            if !node.contains("::") {
+                if let Some(&n) = self.visits.get(&format!("{}::{}", module, node)) {
+                    return Some(n)
+                }
+            }
+        }
+
+        self.visits.get(node).cloned()
+    }
+
+    /// Qualifies the head of a path to a block within the same module, eg: stock to blacksmith::stock
+    fn qualify_head (&self, head: &str) -> Option<String> {
+        let module = self.module.as_ref()?;
+        if head.contains("::") { return None }
+
+        let q = format!("{}::{}", module, head);
+        if self.store.has_block(&q) { Some(q) }
+        else { None }
+    }
+
+    /// Qualifies a full path, eg: stock.price to blacksmith::stock.price
+    fn qualify (&self, lookup: &str) -> Option<String> {
+        let i = lookup.find('.')?;
+        self.qualify_head(&lookup[..i]).map(|head| format!("{}{}", head, &lookup[i..]))
+    }
 }
 
-pub struct Evaluator<'e> {
-    env: &'e mut Env,
+impl<'a> Eval for Scope<'a> {
+    fn get (&self, path: Option<Vec<&str>>, lookup: &str) -> Option<Var> {
+        let v = match path {
+            None => self.locals.get(lookup).cloned(),
+            Some(ref path) => {
+                let mut path = path.clone();
+                let head = self.qualify_head(path[0]);
+                if let Some(ref head) = head { path[0] = head; }
+                self.store.get(Some(path), lookup)
+            },
+        };
+
+        v.or_else(|| self.visit_var(path.as_ref().map(|p| &p[..]), lookup))
+    }
+
+    fn get_last (&self, lookup: &str) -> Option<(Var, bool)> {
+        match self.locals.get(lookup) {
+            Some(&Var::Sym(ref s)) => {
+                let q = self.qualify(s);
+                self.store.get_last(q.as_ref().map_or(s, |q| &q[..]))
+                    .or_else(|| Some((Var::Sym(s.clone()), false)))
+            },
+            Some(v) => Some((v.clone(), true)),
+            None => {
+                let q = self.qualify(lookup);
+                self.store.get_last(q.as_ref().map_or(lookup, |q| &q[..]))
+            }.or_else(|| {
+                let (path, sym) = as_path(lookup);
+                self.visit_var(path.as_ref().map(|p| &p[..]), sym).map(|v| (v, true))
+            }),
+        }
+    }
+
+    fn set (&mut self, path: Option<Vec<&str>>, lookup: &str, var: Var) {
+        match path {
+            None => { self.locals.insert(lookup.to_owned(), var); },
+            Some(mut path) => {
+                let head = self.qualify_head(path[0]);
+                if let Some(ref head) = head { path[0] = head; }
+                self.store.set(Some(path), lookup, var);
+            },
+        }
+    }
+
+    fn clone_path (&mut self, lookup: &str, from: &str) {
+        let lookup_q = self.qualify(lookup);
+        let from_q = if from.contains('.') { self.qualify(from) } else { self.qualify_head(from) };
+        self.store.clone_path(lookup_q.as_ref().map_or(lookup, |q| &q[..]),
+                              from_q.as_ref().map_or(from, |q| &q[..]));
+    }
+
+    fn call (&mut self, var: Var, fun: &str, vars: &Vec<Var>) -> Option<Var> {
+        self.store.call(var, fun, vars)
+    }
+}
+
+/// Changes to another node's locals, applied after running a node
+enum ScopeOp {
+    /// Binds arguments to the called node's parameters
+    Bind(String, Vec<Var>),
+    /// Writes a return value into the caller's locals: caller, called node, value
+    Return(String, String, Var),
+}
+
+/// Default number of steps allowed without emitting, see Evaluator::set_max_steps
+pub const MAX_STEPS: usize = 10_000;
+
+/// Errors during evaluation
+#[derive(Debug,Clone,PartialEq)]
+pub enum EvalError {
+    /// Too many steps were taken without emitting or pausing,
+    /// includes the nodes visited in that time
+    Runaway { steps: usize, nodes: Vec<String> },
+    /// Choice is not part of the pending select or await
+    InvalidChoice(String),
+    /// Nothing is awaiting a choice
+    NotPending(String),
+    /// Interpolated symbol did not resolve, see Evaluator::set_unresolved
+    Unresolved { node: String, symbol: String },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &EvalError::Runaway { steps, ref nodes } => {
+                write!(f, "runaway evaluation after {} steps through nodes: {}",
+                       steps, nodes.join(", "))
+            },
+            &EvalError::InvalidChoice(ref key) => {
+                write!(f, "invalid choice `{}`, not offered by pending select or await", key)
+            },
+            &EvalError::NotPending(ref key) => {
+                write!(f, "invalid choice `{}`, no select or await is pending", key)
+            },
+            &EvalError::Unresolved { ref node, ref symbol } => {
+                write!(f, "{}: unresolved symbol `{}` in string", node, symbol)
+            },
+        }
+    }
+}
+
+impl Error for EvalError {}
+
+pub struct Evaluator<'e, D: 'e = Def> {
+    env: &'e mut Env<D>,
     node_stack: Vec<String>,
+    rng: Box<dyn Rng>,
+
+    max_steps: usize,
+    /// Steps taken since last emit or pause, with nodes visited
+    steps: usize,
+    trail: Vec<String>,
+    error: Option<EvalError>,
+
+    /// Select or await awaiting a choice
+    pending: Option<Next>,
+    /// Select entries whose guard failed
+    disabled: Vec<Choice>,
+    show_disabled: bool,
+    /// Pause following a line, see Evaluator::step
+    queued: Option<Step>,
+
+    unresolved: Unresolved,
+    /// Failed interpolation from the last node run
+    format_error: Option<EvalError>,
+
+    /// Speaker and tags of the last line emitted
+    meta: Meta,
 }
 
-impl<'e> Iterator for Evaluator<'e> {
-        
-        type Item = (Vec<Var>, Option<Next>); //here we only return node name as an option to advance
-        fn next(&mut self) -> Option<Self::Item> {
-            if let Some(nn) = self.node_stack.pop() {
-                if let Some(r) = self.run(&nn) {
-                    // reset node if necessary
-                    if let Some(ref next) = r.1 {
-                        match next {
-                            &Next::Restart(ref nn) => {
-                                if let &Some(ref nn) = nn {
-                                    if let Some(b) = self.env.src.get_mut(nn) {
-                                        b.idx = 0;
-                                    }
-                                }
-                            }
-                            _ => {}, // we handle the rest during Run, for convenience
-                        }
+impl<'e, D: Eval> Iterator for Evaluator<'e, D> {
+    type Item = (Vec<Var>, Option<Next>); //here we only return node name as an option to advance
+
+    /// Ends iteration on runaway evaluation, see Evaluator::error
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.try_next() {
+            Ok(r) => r,
+            Err(e) => { self.error = Some(e); None },
+        }
+    }
+}
+
+impl<'e, D: Eval> Evaluator<'e, D> {
+    /// Evaluator by default starts on the node named 'root'
+    pub fn new (env: &'e mut Env<D>) -> Evaluator<'e, D> {
+        EvaluatorState::new(vec!["root".to_owned()]).to_eval(env)
+    }
+
+    /// Steps through nodes until something is emitted or the stack is empty
+    ///
+    /// Fails when more than the max steps are taken without emitting or pausing
+    /// on await, select, or exit. Flow such as next:now between silent nodes counts
+    /// towards this, even across calls. The current node is kept so evaluation can resume
+    #[allow(clippy::type_complexity)]
+    pub fn try_next (&mut self) -> Result<Option<(Vec<Var>, Option<Next>)>, EvalError> {
+        // moving on skips any pending select
+        self.pending = None;
+        self.disabled.clear();
+        self.queued = None;
+
+        while let Some(nn) = self.node_stack.pop() {
+            if self.steps >= self.max_steps {
+                let e = EvalError::Runaway { steps: self.steps,
+                                             nodes: self.trail.clone() };
+                self.node_stack.push(nn);
+                self.steps = 0;
+                self.trail.clear();
+                return Err(e)
+            }
+
+            self.steps += 1;
+            if !self.trail.contains(&nn) { self.trail.push(nn.clone()); }
+
+            let r = self.run(&nn);
+            if let Some(e) = self.format_error.take() { return Err(e) }
+
+            if let Some(r) = r {
+                // reset node if necessary
+                if let Some(Next::Restart(Some(ref nn))) = r.1 {
+                    if let Some(b) = self.env.src.get_mut(nn) {
+                        b.idx = 0;
                     }
-                    
-                    Some(r)
                 }
-                else { self.next() }
+
+                let paused = matches!(r.1, Some(Next::Await(_)) | Some(Next::Select(_)) | Some(Next::Exit));
+                if paused || !r.0.is_empty() {
+                    self.steps = 0;
+                    self.trail.clear();
+                }
+
+                match r.1 {
+                    Some(Next::Select(_)) | Some(Next::Await(_)) => { self.pending = r.1.clone(); },
+                    _ => {},
+                }
+
+                return Ok(Some(r))
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Steps until a line is emitted or input is awaited
+    ///
+    /// Flow between nodes is followed silently. A line which also awaits is
+    /// returned first, with the await following on the next step
+    pub fn step (&mut self) -> Result<Step, EvalError> {
+        if let Some(step) = self.queued.take() { return Ok(step) }
+
+        loop {
+            let (vars, next) = match self.try_next()? {
+                Some(r) => r,
+                None => return Ok(Step::Finished),
+            };
+
+            let pause = next.as_ref().and_then(|next| self.pause(next));
+
+            if !vars.is_empty() {
+                self.queued = pause;
+                return Ok(Step::Line { vars: vars, meta: self.meta.clone() })
             }
-            else { None }
+            else if let Some(pause) = pause { return Ok(pause) }
         }
     }
 
-impl<'e> Evaluator<'e> {
-    /// Evaluator by default starts on the node named 'root'
-    pub fn new (env: &'e mut Env) -> Evaluator<'e> {
-        Evaluator {
-            env: env,
-            node_stack: vec!["root".to_owned()],
+    /// Step pausing on a select or await
+    fn pause (&self, next: &Next) -> Option<Step> {
+        match next {
+            &Next::Await(ref node) => Some(Step::Await { node: node.clone() }),
+            &Next::Select(ref map) => {
+                let mut options = Choice::from_map(map);
+                if self.show_disabled {
+                    options.extend(self.disabled.iter().cloned());
+                    options.sort_by(|a, b| a.key.cmp(&b.key));
+                }
+
+                Some(Step::Choice { options: options })
+            },
+            _ => None,
         }
     }
 
+    /// Speaker and tags of the last line emitted, also found on Step::Line
+    pub fn meta (&self) -> &Meta {
+        &self.meta
+    }
+
+    /// Select or await waiting on a choice, see Evaluator::select
+    ///
+    /// Cleared once evaluation moves on
+    pub fn pending (&self) -> Option<&Next> {
+        self.pending.as_ref()
+    }
+
+    /// Advances only to a node offered by the pending select or await
+    ///
+    /// Key is the select's option key, or the awaited node's name
+    pub fn select (&mut self, key: &str) -> Result<(), EvalError> {
+        let node = match self.pending {
+            Some(Next::Select(ref map)) => {
+                map.get(key).and_then(|vals| vals.first()).map(|n| n.to_string())
+            },
+            Some(Next::Await(ref node)) => {
+                if node == key { Some(node.clone()) }
+                else { None }
+            },
+            _ => return Err(EvalError::NotPending(key.to_owned())),
+        };
+
+        match node {
+            Some(node) => { self.advance(node); Ok(()) },
+            None => Err(EvalError::InvalidChoice(key.to_owned())),
+        }
+    }
+
+    /// Includes choices whose guard failed in Step::Choice, flagged as disabled
+    ///
+    /// Otherwise they are left out, disabled choices can never be selected
+    pub fn show_disabled (&mut self, show: bool) {
+        self.show_disabled = show;
+    }
+
+    /// Answers a Step::Choice, same as Evaluator::select
+    pub fn choose (&mut self, key: &str) -> Result<(), EvalError> {
+        self.select(key)
+    }
+
+    /// Sets what happens to {symbols} in strings which don't resolve
+    ///
+    /// Defaults to Unresolved::Keep, which leaves them as written
+    pub fn set_unresolved (&mut self, policy: Unresolved) {
+        self.unresolved = policy;
+    }
+
+    /// Sets the number of steps allowed without emitting, defaults to MAX_STEPS
+    pub fn set_max_steps (&mut self, steps: usize) {
+        self.max_steps = steps;
+    }
+
+    /// Error which ended iteration, if any
+    pub fn error (&self) -> Option<&EvalError> {
+        self.error.as_ref()
+    }
+
+    /// Replaces the random source, used for random nodes and chance logic
+    pub fn set_rng<R: Rng + 'static> (&mut self, rng: R) {
+        self.rng = Box::new(rng);
+    }
+
+    /// Seeds the default random source, for deterministic tests and replays
+    pub fn seed (&mut self, seed: u64) {
+        self.set_rng(XorShift::new(seed));
+    }
+
     /// Consumes Evaluator for saving state
     ///
-    /// You should save the Env state as well, as it's external to the Evaluator
+    /// You should save the Env state as well, as it's external to the Evaluator,
+    /// see Evaluator::snapshot to save both together
     pub fn save (self) -> EvaluatorState {
         EvaluatorState {
             node_stack: self.node_stack,
+            pending: self.pending,
+            rng: self.rng.state(),
         }
     }
 
-    /// Manually advances Evaluator to next node
+    /// Manually advances Evaluator to any node, unchecked
+    ///
+    /// Intended for debugging and tooling, use Evaluator::select
+    /// to only advance to what was offered
     pub fn advance (&mut self, node: String) {
         // reset last node on advance
         if let Some(node_) = self.node_stack.pop() {
@@ -118,102 +511,156 @@ impl<'e> Evaluator<'e> {
         }
         
         // successful advance clears out stack
+        self.pending = None;
+        self.queued = None;
         self.node_stack.clear();
         self.node_stack.push(node);
     }
 
-    pub fn resolve (s: &str, logic: &HashMap<String,LogicFn>, def: &HashMap<String,DefBlock>) -> Option<Var> {
-        if let Some(ref lfn) = logic.get(s) {
-            if let Some(val_) = lfn.run(&def, &logic) {
-                return Some(val_.into())
-            }
-        }
-        else if let Some((v,res)) = def.get_last(s) {
-            if res { return Some(v) }
+    /// Manually run the Evaluator, starting at node specified
+    pub fn run (&mut self, node_name: &str)
+                -> Option<(Vec<Var>, Option<Next>)>
+    {
+        let mut op = None;
+        let r = self.run_node(node_name, &mut op);
+
+        match op {
+            Some(ScopeOp::Bind(nn, args)) => {
+                if let Some(b) = self.env.src.get_mut(&nn) {
+                    b.locals.clear(); // fresh scope for each call
+                    for (param, v) in b.params.iter().zip(args) {
+                        b.locals.insert(param.clone(), v);
+                    }
+                }
+            },
+            Some(ScopeOp::Return(caller, nn, v)) => {
+                if let Some(b) = self.env.src.get_mut(&caller) {
+                    b.locals.insert(nn, v);
+                }
+            },
+            None => {},
         }
 
-        None
+        r
     }
 
-    /// Manually run the Evaluator, starting at node specified
-    pub fn run (&mut self, node_name: &str)
-                -> Option<(Vec<Var>, Option<Next>)>
+    fn run_node (&mut self, node_name: &str, op: &mut Option<ScopeOp>)
+                 -> Option<(Vec<Var>, Option<Next>)>
     {
         if let Some(b) = self.env.src.get_mut(node_name) {
             b.visited = true;
+
+            // entering from the start counts as a visit
+            if b.idx == 0 {
+                *self.env.visits.entry(node_name.to_owned()).or_insert(0) += 1;
+            }
             
             if let Some(src) = b.src.get(b.idx) {
+                let line = b.idx;
                 self.node_stack.push(node_name.to_owned()); //more to iterate through?
                 b.idx += 1;
                 
                 match src {
-                    &Src::Or(_,_) => {
-                        if !b.or_valid {
-                            return None
-                        }
-                        else { b.or_valid = false; } //reset
-                    }
+                    &Src::Or(_,_) if !b.or_valid => { return None },
                     &Src::If(_,_,_) => { b.or_valid = true; }
                     // anything else resets above or-logic
                     _ => { b.or_valid = false; },
                 }
 
-                let (mut vars, next) = src.eval(&mut b.logic,
-                                                &mut self.env.def,
-                                                &mut self.env.fun);
-                let has_return = (vars.len() > 0) || next.is_some();
+                let mut scope = Scope { locals: &mut b.locals,
+                                        store: &mut self.env.def,
+                                        visits: &self.env.visits,
+                                        seen: b.emitted.contains(&line),
+                                        module: module_of(&b.name).map(|m| m.to_owned()) };
+
+                // chance is rolled each time it's reached
+                if let &Src::Logic(ref name, Logic::Chance(ref p)) = src {
+                    let hit = match p.get_num(&scope) {
+                        Ok(p) => self.rng.next_f32() < p,
+                        Err(_) => false,
+                    };
+                    b.logic.insert(name.clone(), LogicFn::value(hit));
+                    b.chances.insert(name.clone(), hit);
+                }
+
+                let mut picked = None;
+                let (mut vars, next) = match src {
+                    // sequences pick by how often this line was reached
+                    &Src::Seq(ref seq, ref vals) => {
+                        let count = b.seqs.entry(line).or_insert(0);
+                        let roll = if *seq == Seq::Shuffle { self.rng.next_f32() } else { 0. };
+                        picked = seq.pick(*count, vals.len(), roll);
+                        *count += 1;
+
+                        (picked.and_then(|i| vals.get(i)).cloned().into_iter().collect(), None)
+                    },
+                    _ => src.eval(&mut b.logic, &mut scope, &mut self.env.fun),
+                };
+
+                // random nodes are picked now, and returned as an immediate advance
+                let next = match next {
+                    Some(Next::Random(ref nodes)) => {
+                        rng::choose(&mut *self.rng, nodes).map(|nn| Next::Now(nn.to_owned()))
+                    },
+                    // guarded entries are checked now, only those passing are offered
+                    Some(Next::Select(map)) => {
+                        let mut offered = HashMap::new();
+                        for (key, mut vals) in map {
+                            let pass = match Next::guard(&vals) {
+                                Ok(Some(e)) => { vals.truncate(1); e.test(&scope, &b.logic) == Some(true) },
+                                Ok(None) => true,
+                                Err(_) => false,
+                            };
+
+                            if pass { offered.insert(key, vals); }
+                            else if let Some(node) = vals.first() {
+                                self.disabled.push(Choice { key: key, node: node.to_string(), enabled: false });
+                            }
+                        }
+
+                        // nothing offered continues on
+                        if offered.is_empty() { None }
+                        else { Some(Next::Select(offered)) }
+                    },
+                    next => next,
+                };
+                let has_return = (!vars.is_empty()) || next.is_some();
                
                 // reset when if is successful
                 if has_return { b.or_valid = false; }
+                if !vars.is_empty() {
+                    b.emitted.insert(line);
+                    self.meta = b.meta.get(&line).cloned().unwrap_or_default();
+                }
                 
 
-                for var in vars.iter_mut() {
+                for (i, var) in vars.iter_mut().enumerate() {
                     let mut val = None;
+
+                    // translate before formatting, a sequence emits only what it picked
+                    if let (&mut Var::String(ref mut s), Some(locale)) = (&mut *var, self.env.locale.as_ref()) {
+                        let id = b.line_ids.get(&(line, picked.unwrap_or(i)));
+                        if let Some(text) = id.and_then(|id| locale.translate(id, s)) {
+                            *s = text;
+                        }
+                    }
                     
                     match var {
                         &mut Var::Sym(ref mut s) => { // resolve symbol refs
-                            val = Evaluator::resolve(s, &b.logic, &self.env.def);
+                            val = Evaluator::resolve(s, &b.logic, &scope);
                             // NOTE: otherwise we silently fail
                         },
                         &mut Var::String(ref mut s) => { //format string
-                            let mut fs = String::new();
-                            let mut sym = String::new();
-                            let mut in_sym = false;
-                            
-                            for c in s.chars() {
-                                if (c == ' ' || c == '`') && !sym.is_empty() {
-                                    if let Some(v) = Evaluator::resolve(&sym, &b.logic, &self.env.def) {
-                                        fs.push_str(&v.to_string());
-                                    }
-                                    else {
-                                        fs.push_str(&sym); //push as non-ref sym again
-                                        // NOTE: we should consider failing silently (dont push)
-                                    }
-
-                                    if c == '`' { in_sym = true; }
-                                    else {
-                                        in_sym = false;
-                                        sym.clear();
-                                        fs.push(' ');
-                                    }
-                                }
-                                else if c == '`' { in_sym = true; }
-                                else {
-                                    if in_sym { sym.push(c); }
-                                    else { fs.push(c); }
-                                }
+                            let logic = &b.logic;
+                            // backticks within the text are substituted alongside, for older sources
+                            match format::interpolate(s, self.unresolved,
+                                                      |sym| Evaluator::resolve(sym, logic, &scope)) {
+                                Ok(fs) => { *s = fs; },
+                                Err(sym) => {
+                                    self.format_error = Some(EvalError::Unresolved { node: b.name.clone(),
+                                                                                     symbol: sym });
+                                },
                             }
-
-                            if !sym.is_empty() {
-                                if let Some(v) = Evaluator::resolve(&sym, &b.logic, &self.env.def) {
-                                    fs.push_str(&v.to_string());
-                                }
-                                else {
-                                    fs.push_str(&sym);
-                                }
-                            }
-
-                            *s = fs;
                         },
                         _ => {}
                     }
@@ -232,9 +679,20 @@ impl<'e> Evaluator<'e> {
                             self.node_stack.clear();
                             self.node_stack.push(nn.clone());
                         },
-                        &Next::Call(ref nn) => { // callback nodes add to stack
+                        &Next::Call(ref nn, ref args) => { // callback nodes add to stack
                             b.idx = idx; // reset so we can pickup afterwards
                             self.node_stack.push(nn.clone());
+
+                            let logic = &b.logic;
+                            let args = args.iter().map(|v| resolve_var(v, logic, &scope)).collect();
+                            *op = Some(ScopeOp::Bind(nn.clone(), args));
+                        },
+                        &Next::Return(ref v) => {
+                            self.node_stack.pop();
+                            if let (&Some(ref v), Some(caller)) = (v, self.node_stack.last()) {
+                                let v = resolve_var(v, &b.logic, &scope);
+                                *op = Some(ScopeOp::Return(caller.clone(), b.name.clone(), v));
+                            }
                         },
                         &Next::Back => {
                             self.node_stack.pop();
@@ -251,6 +709,7 @@ impl<'e> Evaluator<'e> {
                         &Next::Await(_) | &Next::Select(_) => {
                             b.idx = idx;
                         },
+                        &Next::Random(_) => {}, // resolved above
                     }
                 }
 
@@ -268,20 +727,111 @@ impl<'e> Evaluator<'e> {
     }
 }
 
-#[derive(Clone,Debug)]
+/// Resolves symbols when possible, otherwise keeps them as is
+fn resolve_var (v: &Var, logic: &HashMap<String,LogicFn>, def: &dyn Eval) -> Var {
+    match v {
+        &Var::Sym(ref s) => Evaluator::resolve(s, logic, def).unwrap_or_else(|| v.clone()),
+        _ => v.clone(),
+    }
+}
+
+impl<'e> Evaluator<'e, Def> {
+    /// Resolves symbol to logic result or var
+    ///
+    /// Any store can be resolved against, not only def blocks
+    pub fn resolve (s: &str, logic: &HashMap<String,LogicFn>, def: &dyn Eval) -> Option<Var> {
+        if let Some(lfn) = logic.get(s) {
+            if let Some(val_) = lfn.run(def, logic) {
+                return Some(val_.into())
+            }
+        }
+        else if let Some((v,res)) = def.get_last(s) {
+            if res { return Some(v) }
+        }
+
+        None
+    }
+
+    /// Snapshot of all mutable runtime state, for save games
+    ///
+    /// See Snapshot::restore to resume on a freshly parsed Env
+    pub fn snapshot (&self) -> Snapshot {
+        let eval = EvaluatorState { node_stack: self.node_stack.clone(),
+                                    pending: self.pending.clone(),
+                                    rng: self.rng.state() };
+        Snapshot { eval: eval, ..Snapshot::new(self.env, &self.node_stack) }
+    }
+}
+
+impl<'e> Evaluator<'e> {
+    /// Reloads source into the environment being evaluated, see Env::reload
+    ///
+    /// Vanished nodes are dropped from the node stack, the rest are kept.
+    /// Pending choices heading to vanished nodes are dropped too, so selecting them is an error
+    pub fn reload (&mut self, parser: Parser) -> Reload {
+        let reload = self.env.reload(parser);
+        self.node_stack.retain(|nn| !reload.vanished.contains(nn));
+
+        let src = &self.env.src;
+        self.pending = match self.pending.take() {
+            Some(Next::Select(mut map)) => {
+                map.retain(|_, vals| vals.first().is_some_and(|n| src.contains_key(&n.to_string())));
+                if map.is_empty() { None }
+                else { Some(Next::Select(map)) }
+            },
+            Some(Next::Await(node)) => {
+                if src.contains_key(&node) { Some(Next::Await(node)) }
+                else { None }
+            },
+            pending => pending,
+        };
+        self.disabled.retain(|c| src.contains_key(&c.node));
+
+        // a pause still to be returned reflects what remains
+        if self.queued.is_some() {
+            self.queued = self.pending.as_ref().and_then(|next| self.pause(next));
+        }
+
+        reload
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EvaluatorState {
     node_stack: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pending: Option<Next>,
+    /// Random state, so rolls carry on as they would have, added in snapshot version 3
+    #[cfg_attr(feature = "serde", serde(default))]
+    rng: Option<u64>,
 }
 
 impl EvaluatorState {
-    pub fn to_eval<'e> (self, env: &'e mut Env) -> Evaluator<'e> {
+    pub fn new (node_stack: Vec<String>) -> EvaluatorState {
+        EvaluatorState { node_stack: node_stack, pending: None, rng: None }
+    }
+
+    pub fn to_eval<'e, D: Eval> (self, env: &'e mut Env<D>) -> Evaluator<'e, D> {
         Evaluator {
             env: env,
             node_stack: self.node_stack,
+            rng: Box::new(self.rng.map(XorShift::new).unwrap_or_else(XorShift::from_time)),
+            max_steps: MAX_STEPS,
+            steps: 0,
+            trail: vec![],
+            error: None,
+            pending: self.pending,
+            disabled: vec![],
+            show_disabled: false,
+            queued: None,
+            unresolved: Unresolved::default(),
+            format_error: None,
+            meta: Meta::default(),
         }
     }
 
-    pub fn as_eval<'e> (&self, env: &'e mut Env) -> Evaluator<'e> {
+    pub fn as_eval<'e, D: Eval> (&self, env: &'e mut Env<D>) -> Evaluator<'e, D> {
         self.clone().to_eval(env)
     }
 }
@@ -0,0 +1,368 @@
+use var::Var;
+use parse::{IR,ParseError,ParseErrorKind};
+use eval::Evaluator;
+use eval::Eval;
+use logic::Logics;
+
+/// Arithmetic operators
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    /// Applies operator to numbers
+    ///
+    /// Integers stay whole unless mixed with floats,
+    /// integer division by zero returns None
+    pub fn apply (&self, left: &Var, right: &Var) -> Option<Var> {
+        match (left, right) {
+            (&Var::Int(l), &Var::Int(r)) => {
+                let n = match *self {
+                    Op::Add => l.checked_add(r),
+                    Op::Sub => l.checked_sub(r),
+                    Op::Mul => l.checked_mul(r),
+                    Op::Div => l.checked_div(r),
+                };
+
+                n.map(Var::Int)
+            },
+            _ => {
+                let l = left.as_num()?;
+                let r = right.as_num()?;
+                let n = match *self {
+                    Op::Add => l + r,
+                    Op::Sub => l - r,
+                    Op::Mul => l * r,
+                    Op::Div => l / r,
+                };
+
+                Some(Var::Num(n as f32))
+            },
+        }
+    }
+}
+
+/// Comparison operators
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum Cmp {
+    GT,
+    LT,
+    GE,
+    LE,
+    EQ,
+    NE,
+    In,
+}
+
+/// Expression tree for logic
+///
+/// Symbols are resolved during evaluation
+#[derive(Debug,PartialEq,Clone)]
+pub enum Expr {
+    Var(Var),
+    /// Arithmetic over numbers, eg: max_hp * 0.5
+    Math(Op, Box<Expr>, Box<Expr>),
+    /// Comparison, always resolves to boolean, eg: gold >= 10
+    ///
+    /// Also tests membership, eg: "sword" in player.inventory
+    Cmp(Cmp, Box<Expr>, Box<Expr>),
+
+    /// Boolean operators, eg: rich and !cursed
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+#[derive(Debug,PartialEq,Clone)]
+enum Tok {
+    Var(Var),
+    Op(Op),
+    Cmp(Cmp),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Tok {
+    fn from_sym (s: &str) -> Option<Tok> {
+        let tok = match s {
+            "+" => Tok::Op(Op::Add),
+            "-" => Tok::Op(Op::Sub),
+            "*" => Tok::Op(Op::Mul),
+            "/" => Tok::Op(Op::Div),
+            ">" => Tok::Cmp(Cmp::GT),
+            "<" => Tok::Cmp(Cmp::LT),
+            ">=" => Tok::Cmp(Cmp::GE),
+            "<=" => Tok::Cmp(Cmp::LE),
+            "==" => Tok::Cmp(Cmp::EQ),
+            "!=" => Tok::Cmp(Cmp::NE),
+            "in" => Tok::Cmp(Cmp::In),
+            "and" => Tok::And,
+            "or" => Tok::Or,
+            "not" => Tok::Not,
+            "(" => Tok::Open,
+            ")" => Tok::Close,
+            _ => return None,
+        };
+
+        Some(tok)
+    }
+}
+
+/// Splits IR into expression tokens
+///
+/// Parenthesis and inversion may be attached to neighboring symbols, eg: (hp + 1) or !cursed
+fn tokenize (mut exp: Vec<IR>) -> Result<Vec<Tok>,ParseError> {
+    let mut toks = vec![];
+    for ir in exp.drain(..) {
+        match ir {
+            IR::Sym(s) => {
+                let mut s = &s[..];
+                while s.len() > 1 && s != "!=" {
+                    if s.starts_with('(') { toks.push(Tok::Open); }
+                    else if s.starts_with('!') { toks.push(Tok::Not); }
+                    else { break }
+                    s = &s[1..];
+                }
+
+                let mut close = 0;
+                while s.len() > 1 && s.ends_with(')') {
+                    close += 1;
+                    s = &s[..s.len()-1];
+                }
+
+                if let Some(tok) = Tok::from_sym(s) { toks.push(tok); }
+                else { toks.push(Tok::Var(Var::parse(IR::Sym(s.to_owned()))?)); }
+
+                for _ in 0..close { toks.push(Tok::Close); }
+            },
+            _ => { toks.push(Tok::Var(Var::parse(ir)?)); },
+        }
+    }
+
+    Ok(toks)
+}
+
+struct ExprParser {
+    toks: Vec<Tok>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn peek (&self) -> Option<&Tok> { self.toks.get(self.pos) }
+
+    fn err (&self) -> ParseError {
+        let e = ParseError::new(ParseErrorKind::InvalidLogic);
+        match self.peek() {
+            Some(&Tok::Var(ref v)) => e.token(v.to_string()),
+            _ => e,
+        }
+    }
+
+    fn parse_or (&mut self) -> Result<Expr,ParseError> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&Tok::Or) {
+            self.pos += 1;
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_and (&mut self) -> Result<Expr,ParseError> {
+        let mut left = self.parse_not()?;
+        while self.peek() == Some(&Tok::And) {
+            self.pos += 1;
+            let right = self.parse_not()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_not (&mut self) -> Result<Expr,ParseError> {
+        if self.peek() == Some(&Tok::Not) {
+            self.pos += 1;
+            let e = self.parse_not()?;
+            return Ok(Expr::Not(Box::new(e)))
+        }
+
+        self.parse_cmp()
+    }
+
+    fn parse_cmp (&mut self) -> Result<Expr,ParseError> {
+        let left = self.parse_sum()?;
+        if let Some(&Tok::Cmp(cmp)) = self.peek() {
+            self.pos += 1;
+            let right = self.parse_sum()?;
+            return Ok(Expr::Cmp(cmp, Box::new(left), Box::new(right)))
+        }
+
+        Ok(left)
+    }
+
+    fn parse_sum (&mut self) -> Result<Expr,ParseError> {
+        let mut left = self.parse_product()?;
+        while let Some(&Tok::Op(op)) = self.peek() {
+            if op != Op::Add && op != Op::Sub { break }
+            self.pos += 1;
+            let right = self.parse_product()?;
+            left = Expr::Math(op, Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_product (&mut self) -> Result<Expr,ParseError> {
+        let mut left = self.parse_atom()?;
+        while let Some(&Tok::Op(op)) = self.peek() {
+            if op != Op::Mul && op != Op::Div { break }
+            self.pos += 1;
+            let right = self.parse_atom()?;
+            left = Expr::Math(op, Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_atom (&mut self) -> Result<Expr,ParseError> {
+        match self.peek().cloned() {
+            Some(Tok::Var(v)) => {
+                self.pos += 1;
+                Ok(Expr::Var(v))
+            },
+            Some(Tok::Open) => {
+                self.pos += 1;
+                let e = self.parse_or()?;
+                if self.peek() != Some(&Tok::Close) {
+                    return Err(ParseError::new(ParseErrorKind::UnbalancedLogic).token("("))
+                }
+                self.pos += 1;
+                Ok(e)
+            },
+            _ => Err(self.err()),
+        }
+    }
+}
+
+impl Expr {
+    /// Parses an expression from IR
+    pub fn parse (exp: Vec<IR>) -> Result<Expr,ParseError> {
+        let toks = tokenize(exp)?;
+        let mut p = ExprParser { toks: toks, pos: 0 };
+        let e = p.parse_or()?;
+
+        if p.pos < p.toks.len() {
+            if p.peek() == Some(&Tok::Close) {
+                return Err(ParseError::new(ParseErrorKind::UnbalancedLogic).token(")"))
+            }
+            return Err(p.err())
+        }
+
+        Ok(e)
+    }
+
+    /// Expression always resolves to a boolean
+    pub fn is_bool (&self) -> bool {
+        matches!(self, &Expr::Cmp(..) | &Expr::And(..) | &Expr::Or(..) | &Expr::Not(..))
+    }
+
+    /// Tests expression as a boolean
+    ///
+    /// Symbols follow Is logic, where existing non-booleans are true and missing are false
+    pub fn test (&self, def: &dyn Eval, logic: &Logics) -> Option<bool> {
+        match self.eval(def, logic) {
+            Some(Var::Bool(b)) => Some(b),
+            Some(_) => {
+                match self {
+                    &Expr::Var(_) => Some(true),
+                    _ => None,
+                }
+            },
+            None => {
+                match self {
+                    &Expr::Var(Var::Sym(_)) => Some(false),
+                    _ => None,
+                }
+            },
+        }
+    }
+
+    /// Evaluates expression, resolving any symbols
+    ///
+    /// Returns None when a symbol or operation cannot be resolved
+    pub fn eval (&self, def: &dyn Eval, logic: &Logics) -> Option<Var> {
+        match self {
+            &Expr::Var(Var::Sym(ref s)) => {
+                Evaluator::resolve(s, logic, def)
+            },
+            &Expr::Var(ref v) => Some(v.clone()),
+            &Expr::Math(op, ref left, ref right) => {
+                let left = left.eval(def, logic)?;
+                let right = right.eval(def, logic)?;
+                op.apply(&left, &right)
+            },
+            &Expr::Cmp(cmp, ref left, ref right) => {
+                let left = left.eval(def, logic)?;
+                let right = right.eval(def, logic)?;
+
+                let r = match (cmp, left.as_num(), right.as_num()) {
+                    (Cmp::In, _, _) => right.contains(&left)?,
+                    (Cmp::EQ, Some(l), Some(r)) => l == r,
+                    (Cmp::NE, Some(l), Some(r)) => l != r,
+                    (Cmp::EQ, _, _) => left == right,
+                    (Cmp::NE, _, _) => left != right,
+                    (_, Some(l), Some(r)) => {
+                        match cmp {
+                            Cmp::GT => l > r,
+                            Cmp::LT => l < r,
+                            Cmp::GE => l >= r,
+                            Cmp::LE => l <= r,
+                            _ => unreachable!(),
+                        }
+                    },
+                    _ => return None, // cannot order non-numbers
+                };
+
+                Some(Var::Bool(r))
+            },
+            &Expr::And(ref left, ref right) => {
+                let r = left.test(def, logic) == Some(true) &&
+                    right.test(def, logic) == Some(true);
+                Some(Var::Bool(r))
+            },
+            &Expr::Or(ref left, ref right) => {
+                let r = left.test(def, logic) == Some(true) ||
+                    right.test(def, logic) == Some(true);
+                Some(Var::Bool(r))
+            },
+            &Expr::Not(ref e) => {
+                e.test(def, logic).map(|b| Var::Bool(!b))
+            },
+        }
+    }
+
+    /// Collects all symbols referenced in expression
+    pub fn syms (&self) -> Vec<&str> {
+        let mut v = vec![];
+        match self {
+            &Expr::Var(Var::Sym(ref s)) => { v.push(&s[..]); },
+            &Expr::Var(_) => {},
+            &Expr::Math(_, ref l, ref r) | &Expr::Cmp(_, ref l, ref r) |
+            &Expr::And(ref l, ref r) | &Expr::Or(ref l, ref r) => {
+                v.extend(l.syms());
+                v.extend(r.syms());
+            },
+            &Expr::Not(ref e) => { v.extend(e.syms()); },
+        }
+
+        v
+    }
+}
@@ -0,0 +1,234 @@
+use var::Var;
+
+/// What to do with an interpolated symbol that does not resolve
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub enum Unresolved {
+    /// Leaves the reference as written, braces included, eg: {player.name}
+    #[default]
+    Keep,
+    /// Leaves nothing in its place
+    Empty,
+    /// Fails evaluation with EvalError::Unresolved
+    Error,
+}
+
+/// Format specifier following a symbol, eg: {gold:>6.1}
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct Spec {
+    /// One of '<', '>', or '^', numbers align right by default
+    pub align: Option<char>,
+    pub width: usize,
+    /// Decimal places for numbers
+    pub precision: Option<usize>,
+}
+
+impl Spec {
+    /// Parses a specifier, without the leading colon
+    ///
+    /// Returns None when it is not a specifier
+    pub fn parse (s: &str) -> Option<Spec> {
+        let mut spec = Spec::default();
+        let mut rest = s;
+
+        if let Some(c) = rest.chars().next() {
+            if ['<', '>', '^'].contains(&c) {
+                spec.align = Some(c);
+                rest = &rest[1..];
+            }
+        }
+
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if digits > 0 { spec.width = rest[..digits].parse().ok()?; }
+        rest = &rest[digits..];
+
+        if let Some(p) = rest.strip_prefix('.') {
+            if p.is_empty() { return None }
+            spec.precision = Some(p.parse().ok()?);
+            rest = "";
+        }
+
+        if rest.is_empty() && !s.is_empty() { Some(spec) }
+        else { None }
+    }
+
+    /// Formats a var according to the specifier
+    pub fn apply (&self, v: &Var) -> String {
+        let s = match (v, self.precision) {
+            (&Var::Num(n), Some(p)) => format!("{:.*}", p, n),
+            (&Var::Int(n), Some(p)) => format!("{:.*}", p, n as f64),
+            _ => v.to_string(),
+        };
+
+        let len = s.chars().count();
+        if len >= self.width { return s }
+
+        let pad = self.width - len;
+        let numeric = matches!(v, &Var::Num(_) | &Var::Int(_));
+        let (left, right) = match self.align {
+            Some('<') => (0, pad),
+            Some('^') => (pad / 2, pad - pad / 2),
+            Some(_) => (pad, 0),
+            None => if numeric { (pad, 0) } else { (0, pad) },
+        };
+
+        format!("{}{}{}", " ".repeat(left), s, " ".repeat(right))
+    }
+}
+
+/// An interpolated reference, eg: {gold:.0|# coin|# coins}
+#[derive(Debug,Clone,PartialEq)]
+pub struct Field {
+    pub sym: String,
+    pub spec: Spec,
+    /// Singular and plural forms, '#' is replaced with the formatted value
+    pub plural: Option<(String, String)>,
+}
+
+impl Field {
+    pub fn parse (s: &str) -> Field {
+        let mut parts = s.splitn(3, '|');
+        let head = parts.next().unwrap_or("").trim();
+        let plural = match (parts.next(), parts.next()) {
+            (Some(one), Some(many)) => Some((one.to_owned(), many.to_owned())),
+            (Some(one), None) => Some((one.to_owned(), one.to_owned())),
+            _ => None,
+        };
+
+        // NOTE: symbols may contain colons themselves, eg: visited:intro
+        if let Some(i) = head.rfind(':') {
+            if let Some(spec) = Spec::parse(&head[i+1..]) {
+                return Field { sym: head[..i].to_owned(), spec: spec, plural: plural }
+            }
+        }
+
+        Field { sym: head.to_owned(), spec: Spec::default(), plural: plural }
+    }
+
+    pub fn apply (&self, v: &Var) -> String {
+        let s = self.spec.apply(v);
+        match self.plural {
+            Some((ref one, ref many)) => {
+                let singular = v.as_num() == Some(1.);
+                let form = if singular { one } else { many };
+                form.replace('#', &s)
+            },
+            None => s,
+        }
+    }
+}
+
+/// Pieces of a string to interpolate
+#[derive(Debug,Clone,PartialEq)]
+pub enum Piece {
+    Text(String),
+    /// Field with the reference as written, braces included
+    Field(Field, String),
+}
+
+/// Splits a string into text and fields
+///
+/// Braces are escaped by doubling them, eg: {{ and }}, unclosed braces
+/// and those without a symbol, eg: {} or {:>5}, are kept as text
+pub fn pieces (s: &str) -> Vec<Piece> {
+    let mut pieces = vec![];
+    let mut text = String::new();
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '{' if chars.peek().map(|n| n.1) == Some('{') => { chars.next(); text.push('{'); },
+            '}' if chars.peek().map(|n| n.1) == Some('}') => { chars.next(); text.push('}'); },
+            '{' => {
+                match s[i+1..].find(['{', '}']) {
+                    Some(end) if s[i+1+end..].starts_with('}') => {
+                        let raw = &s[i..i+end+2];
+                        let field = Field::parse(&raw[1..raw.len()-1]);
+                        if field.sym.is_empty() { text.push_str(raw); }
+                        else {
+                            if !text.is_empty() { pieces.push(Piece::Text(text.clone())); text.clear(); }
+                            pieces.push(Piece::Field(field, raw.to_owned()));
+                        }
+
+                        while let Some(&(j, _)) = chars.peek() {
+                            if j > i + end + 1 { break }
+                            chars.next();
+                        }
+                    },
+                    _ => text.push(c),
+                }
+            },
+            _ => text.push(c),
+        }
+    }
+
+    if !text.is_empty() { pieces.push(Piece::Text(text)); }
+
+    pieces
+}
+
+/// Interpolates fields within a string, resolving symbols through lookup
+///
+/// Backtick references within the text between fields are substituted too,
+/// interpolated values are never scanned again.
+/// Fails with the first unresolved symbol when the policy is Unresolved::Error
+pub fn interpolate<F> (s: &str, policy: Unresolved, mut lookup: F) -> Result<String, String>
+    where F: FnMut(&str) -> Option<Var>
+{
+    let mut fs = String::new();
+    for piece in pieces(s) {
+        match piece {
+            Piece::Text(t) => fs.push_str(&backticks(&t, &mut lookup)),
+            Piece::Field(field, raw) => {
+                match lookup(&field.sym) {
+                    Some(v) => fs.push_str(&field.apply(&v)),
+                    None => match policy {
+                        Unresolved::Keep => fs.push_str(&raw),
+                        Unresolved::Empty => {},
+                        Unresolved::Error => return Err(field.sym),
+                    },
+                }
+            },
+        }
+    }
+
+    Ok(fs)
+}
+
+/// Substitutes backtick references, eg: `player.name, kept for older sources
+///
+/// References end at a space or another backtick, and echo their name when unresolved
+pub fn backticks<F> (s: &str, mut lookup: F) -> String
+    where F: FnMut(&str) -> Option<Var>
+{
+    let mut fs = String::new();
+    let mut sym = String::new();
+    let mut in_sym = false;
+
+    for c in s.chars() {
+        if (c == ' ' || c == '`') && !sym.is_empty() {
+            match lookup(&sym) {
+                Some(v) => fs.push_str(&v.to_string()),
+                None => fs.push_str(&sym), // push as non-ref sym again
+            }
+
+            if c == '`' { in_sym = true; }
+            else {
+                in_sym = false;
+                fs.push(' ');
+            }
+            sym.clear();
+        }
+        else if c == '`' { in_sym = true; }
+        else if in_sym { sym.push(c); }
+        else { fs.push(c); }
+    }
+
+    if !sym.is_empty() {
+        match lookup(&sym) {
+            Some(v) => fs.push_str(&v.to_string()),
+            None => fs.push_str(&sym),
+        }
+    }
+
+    fs
+}
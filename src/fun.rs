@@ -1,14 +1,15 @@
 use var::Var;
-use def::Def;
+use eval::Eval;
 
-pub struct Fun(Box<dyn FnMut(&[Var], &Def) -> Option<Var> + Send>);
+#[allow(clippy::type_complexity)]
+pub struct Fun(Box<dyn FnMut(&[Var], &dyn Eval) -> Option<Var> + Send>);
 impl Fun {
-    pub fn run(&mut self, args: &[Var], def: &Def) -> Option<Var> {
+    pub fn run(&mut self, args: &[Var], def: &dyn Eval) -> Option<Var> {
         self.0(args, def)
     }
 
-    pub fn new<F: 'static + Send>(fun: F) -> Fun
-        where F: FnMut(&[Var], &Def) -> Option<Var> {
+    pub fn new<F>(fun: F) -> Fun
+        where F: FnMut(&[Var], &dyn Eval) -> Option<Var> + 'static + Send {
         Fun(Box::new(fun))
     }
 }
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path,PathBuf,Component};
+
+/// Loads included source files, see Parser::parse_file
+///
+/// Implement this to load from embedded bytes, archives, or a virtual file system
+pub trait Resolver {
+    /// Loads source found at a path, as given by Resolver::join
+    fn load (&mut self, path: &str) -> io::Result<String>;
+
+    /// Path of an include, relative to the including file
+    ///
+    /// By default paths are '/' separated, with '.' and '..' resolved
+    fn join (&self, from: &str, path: &str) -> String {
+        join(from, path)
+    }
+}
+
+/// Joins an include path onto the directory of the including file
+///
+/// eg: 'town/square.ls' including '../shop.ls' becomes 'shop.ls'
+pub fn join (from: &str, path: &str) -> String {
+    let mut parts: Vec<&str> = if path.starts_with('/') { vec![""] } else {
+        let mut dir: Vec<&str> = from.split('/').collect();
+        dir.pop(); // file name
+        dir
+    };
+
+    for part in path.split('/') {
+        match part {
+            "" | "." => {},
+            ".." => {
+                match parts.last() {
+                    Some(&"..") | None => parts.push(".."),
+                    Some(&"") if parts.len() == 1 => {}, // root
+                    Some(_) => { parts.pop(); },
+                }
+            },
+            _ => parts.push(part),
+        }
+    }
+
+    parts.join("/")
+}
+
+/// Loads includes from disk, relative to a root directory
+///
+/// Paths leading outside of the root are refused, eg: '/etc/passwd' or '../../x'
+#[derive(Debug,Clone)]
+pub struct FileResolver {
+    pub root: PathBuf,
+}
+
+impl FileResolver {
+    pub fn new<P: Into<PathBuf>> (root: P) -> FileResolver {
+        FileResolver { root: root.into() }
+    }
+
+    /// Location of a path on disk, failing when it leads outside of the root
+    ///
+    /// Symbolic links are followed before checking, so the file must exist
+    pub fn resolve (&self, path: &str) -> io::Result<PathBuf> {
+        let outside = || io::Error::new(io::ErrorKind::PermissionDenied,
+                                        format!("{} leads outside of the include root", path));
+
+        let mut rel = PathBuf::new();
+        for part in Path::new(path).components() {
+            match part {
+                Component::Normal(p) => rel.push(p),
+                Component::CurDir => {},
+                Component::ParentDir => { if !rel.pop() { return Err(outside()) } },
+                Component::RootDir | Component::Prefix(_) => return Err(outside()),
+            }
+        }
+
+        let full = self.root.join(rel).canonicalize()?;
+        if full.starts_with(self.root.canonicalize()?) { Ok(full) }
+        else { Err(outside()) }
+    }
+}
+
+impl Resolver for FileResolver {
+    fn load (&mut self, path: &str) -> io::Result<String> {
+        fs::read_to_string(self.resolve(path)?)
+    }
+}
+
+/// Loads includes from memory, for embedded sources and tests
+///
+/// eg: resolver.insert("shop.ls", include_str!("shop.ls"))
+#[derive(Debug,Clone,Default)]
+pub struct MemResolver {
+    pub files: HashMap<String, String>,
+}
+
+impl MemResolver {
+    pub fn new () -> MemResolver {
+        MemResolver::default()
+    }
+
+    pub fn insert<P: Into<String>, S: Into<String>> (&mut self, path: P, src: S) {
+        self.files.insert(path.into(), src.into());
+    }
+}
+
+impl Resolver for MemResolver {
+    fn load (&mut self, path: &str) -> io::Result<String> {
+        match self.files.get(path) {
+            Some(src) => Ok(src.clone()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, path.to_owned())),
+        }
+    }
+}
@@ -1,3 +1,7 @@
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+
 pub mod parse;
 pub mod eval;
 pub mod source;
@@ -6,3 +10,11 @@ pub mod logic;
 pub mod env;
 pub mod def;
 pub mod fun;
+pub mod lint;
+pub mod expr;
+pub mod snapshot;
+pub mod rng;
+pub mod step;
+pub mod locale;
+pub mod format;
+pub mod include;
@@ -0,0 +1,428 @@
+use std::collections::{HashMap,HashSet,BTreeSet};
+use std::fmt;
+
+use env::{Env,module_of};
+use source::{Src,Next};
+use logic::Logic;
+use var::{Var,Mut};
+use parse::SrcBlock;
+use def::{self,DefBlock};
+use format::{self,Piece};
+
+/// Static warnings found when validating an environment
+#[derive(Debug,Clone,PartialEq)]
+pub enum Warning {
+    /// No root node to begin evaluation on
+    MissingRoot,
+    /// Next or select references a node not found in environment
+    DanglingNode { block: String, node: String },
+    /// If, When, or logic references logic not defined in block
+    UndefinedLogic { block: String, logic: String },
+    /// References a def path not found in def blocks or mutations
+    UndefinedDef { block: String, path: String },
+    /// Node can never be reached from root
+    Unreachable { block: String },
+    /// Def entry is never referenced
+    UnusedDef { block: String, key: String },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Warning::MissingRoot => write!(f, "missing root node"),
+            &Warning::DanglingNode { ref block, ref node } => {
+                write!(f, "{}: next references unknown node `{}`", block, node)
+            },
+            &Warning::UndefinedLogic { ref block, ref logic } => {
+                write!(f, "{}: undefined logic `{}`", block, logic)
+            },
+            &Warning::UndefinedDef { ref block, ref path } => {
+                write!(f, "{}: undefined def path `{}`", block, path)
+            },
+            &Warning::Unreachable { ref block } => {
+                write!(f, "{}: node is unreachable from root", block)
+            },
+            &Warning::UnusedDef { ref block, ref key } => {
+                write!(f, "{}: def entry `{}` is never used", block, key)
+            },
+        }
+    }
+}
+
+/// Reference kinds found in source
+enum Ref {
+    Node(String),
+    /// Must resolve to logic or def path
+    Logic(String),
+    /// Resolved if possible, otherwise passed along as a symbol
+    Var(String),
+    /// Must resolve to a def path
+    Path(String),
+    /// Def path being written to
+    Write(String),
+    /// Def block cloned into a path
+    New(String, String),
+    /// Node called, whose return value becomes a local
+    Call(String),
+}
+
+/// Symbols referenced in a formatted string, see Evaluator::run
+pub fn format_refs (s: &str) -> Vec<String> {
+    let mut refs = vec![];
+    for piece in format::pieces(s) {
+        let t = match piece {
+            Piece::Field(field, _) => { refs.push(field.sym); continue },
+            Piece::Text(t) => t,
+        };
+
+        // backticks are only substituted within text
+        let mut sym = String::new();
+        let mut in_sym = false;
+        for c in t.chars() {
+            if (c == ' ' || c == '`') && !sym.is_empty() {
+                refs.push(sym.clone());
+                sym.clear();
+                in_sym = c == '`';
+            }
+            else if c == '`' { in_sym = true; }
+            else if in_sym { sym.push(c); }
+        }
+
+        if !sym.is_empty() { refs.push(sym); }
+    }
+
+    refs
+}
+
+fn next_refs (next: &Next, refs: &mut Vec<Ref>) {
+    match next {
+        &Next::Now(ref nn) | &Next::Await(ref nn) => {
+            refs.push(Ref::Node(nn.clone()));
+        },
+        &Next::Call(ref nn, ref args) => {
+            refs.push(Ref::Node(nn.clone()));
+            refs.push(Ref::Call(nn.clone()));
+            var_refs(args, refs);
+        },
+        &Next::Return(Some(ref v)) => { var_refs(::std::slice::from_ref(v), refs); },
+        &Next::Restart(Some(ref nn)) => { refs.push(Ref::Node(nn.clone())); },
+        &Next::Random(ref nodes) => {
+            for &(ref nn, _) in nodes.iter() { refs.push(Ref::Node(nn.clone())); }
+        },
+        &Next::Select(ref map) => {
+            for vals in map.values() {
+                // first value is the node selected
+                if let Some(v) = vals.first() {
+                    refs.push(Ref::Node(v.to_string()));
+                }
+
+                if let Ok(Some(e)) = Next::guard(vals) {
+                    for s in e.syms() { refs.push(Ref::Logic(s.to_owned())); }
+                }
+            }
+        },
+        _ => {},
+    }
+}
+
+fn var_refs (vars: &[Var], refs: &mut Vec<Ref>) {
+    for v in vars {
+        match v {
+            &Var::Sym(ref s) => { refs.push(Ref::Var(s.clone())); },
+            &Var::String(ref s) => {
+                for sym in format_refs(s) { refs.push(Ref::Path(sym)); }
+            },
+            _ => {},
+        }
+    }
+}
+
+fn mut_refs (m: &Mut, v: &str, a: &[Var], refs: &mut Vec<Ref>) {
+    match m {
+        &Mut::Add | &Mut::Sub | &Mut::Mul | &Mut::Div => {
+            // NOTE: math on a missing var does not build it
+            refs.push(Ref::Path(v.to_owned()));
+            if let Some(&Var::Sym(ref s)) = a.first() {
+                refs.push(Ref::Path(s.clone()));
+            }
+            return
+        },
+        &Mut::New => {
+            if let Some(&Var::Sym(ref s)) = a.first() {
+                refs.push(Ref::New(v.to_owned(), s.clone()));
+            }
+        },
+        &Mut::Swap => {},
+        &Mut::Push | &Mut::Remove => {
+            refs.push(Ref::Var(v.to_owned()));
+            for n in a {
+                if let &Var::Sym(ref s) = n { refs.push(Ref::Var(s.clone())); }
+            }
+        },
+        &Mut::Contains => {
+            for n in a {
+                if let &Var::Sym(ref s) = n { refs.push(Ref::Var(s.clone())); }
+            }
+        },
+        &Mut::Fn(_) => {
+            for n in a {
+                if let &Var::Sym(ref s) = n { refs.push(Ref::Var(s.clone())); }
+            }
+        },
+    }
+
+    refs.push(Ref::Write(v.to_owned()));
+}
+
+fn src_refs (src: &Src, refs: &mut Vec<Ref>) {
+    match src {
+        &Src::Logic(_, ref logic) => {
+            match logic {
+                &Logic::GT(ref l, ref r) | &Logic::LT(ref l, ref r) => {
+                    for v in [l, r].iter() {
+                        if let &&Var::Sym(ref s) = v { refs.push(Ref::Path(s.clone())); }
+                    }
+                },
+                &Logic::Is(ref s) | &Logic::IsNot(ref s) => {
+                    // NOTE: missing paths are valid here, they test for existence
+                    refs.push(Ref::Var(s.clone()));
+                    if !s.contains('.') { refs.push(Ref::Logic(s.clone())); }
+                },
+                &Logic::Composite(_, ref lookups) => {
+                    for s in lookups { refs.push(Ref::Logic(s.clone())); }
+                },
+                &Logic::Expr(ref e) => {
+                    for s in e.syms() { refs.push(Ref::Logic(s.to_owned())); }
+                },
+                &Logic::Chance(ref p) => {
+                    if let &Var::Sym(ref s) = p { refs.push(Ref::Path(s.clone())); }
+                },
+            }
+        },
+        &Src::If(ref lookup, ref vars, ref next) => {
+            refs.push(Ref::Logic(lookup.clone()));
+            var_refs(vars, refs);
+            if let &Some(ref next) = next { next_refs(next, refs); }
+        },
+        &Src::Or(ref vars, ref next) => {
+            var_refs(vars, refs);
+            if let &Some(ref next) = next { next_refs(next, refs); }
+        },
+        &Src::Emit(ref vars) | &Src::Seq(_, ref vars) => { var_refs(vars, refs); },
+        &Src::Next(ref next) => { next_refs(next, refs); },
+        &Src::Mut(ref m, ref v, ref a) => { mut_refs(m, v, a, refs); },
+        &Src::When(ref map) => {
+            for (k, &(ref m, ref v, ref a)) in map.iter() {
+                refs.push(Ref::Logic(k.clone()));
+                mut_refs(m, v, a, refs);
+            }
+        },
+    }
+}
+
+/// Collects all def blocks, including nested ones, by their full path
+fn def_blocks<'d> (path: String, block: &'d DefBlock, blocks: &mut Vec<(String, &'d DefBlock)>) {
+    for (name, b) in block.blocks.iter() {
+        def_blocks(format!("{}.{}", path, name), b, blocks);
+    }
+    blocks.push((path, block));
+}
+
+/// Inserts all paths within a def block
+fn insert_paths (path: &str, block: &DefBlock, paths: &mut HashSet<String>) {
+    let mut blocks = vec![];
+    def_blocks(path.to_owned(), block, &mut blocks);
+    for (path, b) in blocks {
+        for key in b.data.keys() {
+            paths.insert(format!("{}.{}", path, key));
+        }
+        paths.insert(path);
+    }
+}
+
+/// Node whose visits are read, eg: 'visited:intro' or 'intro.visits'
+fn visit_node (s: &str) -> Option<&str> {
+    s.strip_prefix("visited:").or_else(|| s.strip_suffix(".visits"))
+}
+
+/// Qualifies a path read from within a module, as done when evaluating
+fn qualify (env: &Env, module: Option<&str>, s: &str) -> String {
+    if let (Some(module), Some(i)) = (module, s.find('.')) {
+        let head = format!("{}::{}", module, &s[..i]);
+        if !s[..i].contains("::") && env.def.contains_key(&head) {
+            return format!("{}{}", head, &s[i..])
+        }
+    }
+
+    s.to_owned()
+}
+
+/// Qualifies a def block cloned from within a module, eg: new stock
+fn qualify_block (env: &Env, module: Option<&str>, block: &str) -> String {
+    if block.contains('.') { return qualify(env, module, block) }
+    match module {
+        Some(m) if !block.contains("::") && env.def.contains_key(&format!("{}::{}", m, block)) => {
+            format!("{}::{}", m, block)
+        },
+        _ => block.to_owned(),
+    }
+}
+
+fn block_refs (b: &SrcBlock) -> Vec<Ref> {
+    let mut refs = vec![];
+    for src in b.src.iter() { src_refs(src, &mut refs); }
+    refs
+}
+
+/// Walks all source blocks and reports structural warnings
+pub fn validate (env: &Env) -> Vec<Warning> {
+    let mut warnings = vec![];
+
+    let mut names: Vec<&String> = env.src.keys().collect();
+    names.sort();
+
+    let refs: HashMap<String, Vec<Ref>> = names.iter()
+        .map(|name| ((*name).clone(), block_refs(&env.src[*name])))
+        .collect();
+
+    // all def paths available, including those built from mutations
+    let mut blocks = vec![];
+    for (name, def) in env.def.iter() {
+        def_blocks(name.clone(), def, &mut blocks);
+    }
+    blocks.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut paths: HashSet<String> = HashSet::new();
+    for &(ref path, block) in blocks.iter() {
+        insert_paths(path, block, &mut paths);
+    }
+    for (name, r) in refs.iter() {
+        let module = module_of(name);
+        for r in r.iter() {
+            match r {
+                &Ref::Write(ref path) => { paths.insert(qualify(env, module, path)); },
+                &Ref::New(ref path, ref block) => {
+                    let path = qualify(env, module, path);
+                    paths.insert(path.clone());
+                    let block = qualify_block(env, module, block);
+                    let from: Vec<&str> = block.split_terminator('.').collect();
+                    if let Some(def) = def::find_block(&env.def, &from) {
+                        insert_paths(&path, def, &mut paths);
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    // def entries read from anywhere
+    let mut used: HashSet<String> = HashSet::new();
+    let mut used_blocks: HashSet<String> = HashSet::new();
+    for &(_, block) in blocks.iter() {
+        for v in block.data.values() {
+            if let &Var::Sym(ref s) = v { used.insert(s.clone()); }
+        }
+    }
+
+    for name in names.iter() {
+        let b = &env.src[*name];
+        let mut logic: HashSet<&str> = b.src.iter().filter_map(|s| match s {
+            &Src::Logic(ref n, _) => Some(&n[..]),
+            _ => None,
+        }).collect();
+
+        // node-local variables, from parameters and called nodes' returns
+        logic.extend(b.params.iter().map(|p| &p[..]));
+        logic.insert("seen");
+        for r in refs[*name].iter() {
+            if let &Ref::Call(ref nn) = r { logic.insert(&nn[..]); }
+        }
+
+        let module = module_of(&b.name);
+        let dangling = |b: &SrcBlock, node: &str| {
+            let local = module.map(|m| format!("{}::{}", m, node));
+            if env.src.contains_key(node) || local.is_some_and(|n| env.src.contains_key(&n)) { None }
+            else { Some(Warning::DanglingNode { block: b.name.clone(), node: node.to_owned() }) }
+        };
+
+        let mut reported = BTreeSet::new(); // avoid repeats within a block
+        for r in refs[*name].iter() {
+            let warning = match r {
+                &Ref::Node(ref node) => dangling(b, node),
+                &Ref::Logic(ref s) => {
+                    let s = &qualify(env, module, s);
+                    used.insert(s.clone());
+                    if logic.contains(&s[..]) || paths.contains(s) { None }
+                    else if let Some(node) = visit_node(s) { dangling(b, node) }
+                    else if s.contains('.') {
+                        Some(Warning::UndefinedDef { block: b.name.clone(), path: s.clone() })
+                    }
+                    else { Some(Warning::UndefinedLogic { block: b.name.clone(), logic: s.clone() }) }
+                },
+                &Ref::Var(ref s) => { used.insert(qualify(env, module, s)); None },
+                &Ref::Path(ref s) => {
+                    let s = &qualify(env, module, s);
+                    used.insert(s.clone());
+                    if logic.contains(&s[..]) || paths.contains(s) { None }
+                    else if let Some(node) = visit_node(s) { dangling(b, node) }
+                    else { Some(Warning::UndefinedDef { block: b.name.clone(), path: s.clone() }) }
+                },
+                &Ref::New(_, ref block) => {
+                    let block = &qualify_block(env, module, block);
+                    used_blocks.insert(block.clone());
+                    let from: Vec<&str> = block.split_terminator('.').collect();
+                    if def::find_block(&env.def, &from).is_some() { None }
+                    else { Some(Warning::UndefinedDef { block: b.name.clone(), path: block.clone() }) }
+                },
+                &Ref::Write(_) | &Ref::Call(_) => None,
+            };
+
+            if let Some(w) = warning {
+                if reported.insert(w.to_string()) { warnings.push(w); }
+            }
+        }
+    }
+
+    // reachability from root
+    if env.src.contains_key("root") {
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut stack = vec!["root"];
+        while let Some(name) = stack.pop() {
+            if !seen.insert(name) { continue }
+            if let Some(r) = refs.get(name) {
+                for r in r.iter() {
+                    if let &Ref::Node(ref node) = r {
+                        if env.src.contains_key(node) { stack.push(node); }
+                    }
+                }
+            }
+        }
+
+        for name in names.iter() {
+            if !seen.contains(&name[..]) {
+                warnings.push(Warning::Unreachable { block: (*name).clone() });
+            }
+        }
+    }
+    else { warnings.push(Warning::MissingRoot); }
+
+    // unused def entries
+    for &(ref name, def) in blocks.iter() {
+        // cloned blocks are considered used entirely
+        let cloned = used_blocks.iter().any(|b| {
+            name == b || name.starts_with(&format!("{}.", b))
+        });
+        if cloned { continue }
+        
+        let mut keys: Vec<&String> = def.data.keys().collect();
+        keys.sort();
+        for key in keys {
+            let path = format!("{}.{}", name, key);
+            if !used.contains(&path) {
+                warnings.push(Warning::UnusedDef { block: name.clone(), key: key.clone() });
+            }
+        }
+    }
+
+    warnings
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use env::Env;
+use source::Src;
+use var::Var;
+
+/// Prefix for an explicit line ID following a string, eg: emit "Halt!" line:guard_halt
+pub const LINE_TAG: &str = "line:";
+
+/// Translates emitted strings, see Env::set_locale
+///
+/// Applied before backtick formatting, so translations may reference variables too
+pub trait Locale: Send {
+    /// Translated text for a line ID, the source text is given for fallbacks
+    ///
+    /// Returning None keeps the source text
+    fn translate (&self, id: &str, text: &str) -> Option<String>;
+}
+
+/// Translations keyed by line ID, see parse_csv
+impl Locale for HashMap<String, String> {
+    fn translate (&self, id: &str, _text: &str) -> Option<String> {
+        self.get(id).cloned()
+    }
+}
+
+/// Generated line ID, stable as long as the block name and text are unchanged
+///
+/// eg: 'guard:9f2c01ab'
+pub fn line_id (block: &str, text: &str) -> String {
+    // NOTE: FNV-1a, std hashers are not guaranteed to be stable between releases
+    let mut hash: u32 = 0x811c_9dc5;
+    for b in text.bytes() {
+        hash ^= b as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+
+    format!("{}:{:08x}", block, hash)
+}
+
+/// Emitted variables of a source statement, if any
+pub fn src_vars (src: &Src) -> Option<&Vec<Var>> {
+    match src {
+        &Src::Emit(ref v) | &Src::Seq(_, ref v) | &Src::If(_, ref v, _) | &Src::Or(ref v, _) => Some(v),
+        _ => None,
+    }
+}
+
+fn src_vars_mut (src: &mut Src) -> Option<&mut Vec<Var>> {
+    match src {
+        &mut Src::Emit(ref mut v) | &mut Src::Seq(_, ref mut v) |
+        &mut Src::If(_, ref mut v, _) | &mut Src::Or(ref mut v, _) => Some(v),
+        _ => None,
+    }
+}
+
+/// Assigns IDs to every string a statement emits, by var index
+///
+/// Explicit IDs following a string are taken out of the statement,
+/// the rest are generated from the block name and text
+pub fn assign_ids (block: &str, src: &mut Src) -> Vec<(usize, String)> {
+    let mut ids = vec![];
+    let vars = match src_vars_mut(src) {
+        Some(v) => v,
+        None => return ids,
+    };
+
+    let mut i = 0;
+    while i < vars.len() {
+        if let Var::String(ref s) = vars[i] {
+            let id = match vars.get(i + 1) {
+                Some(&Var::Sym(ref t)) => t.strip_prefix(LINE_TAG).map(|id| id.to_owned()),
+                _ => None,
+            };
+
+            match id {
+                Some(id) => { vars.remove(i + 1); ids.push((i, id)); },
+                None => { ids.push((i, line_id(block, s))); },
+            }
+        }
+
+        i += 1;
+    }
+
+    ids
+}
+
+/// Entry within a string table
+#[derive(Debug,Clone,PartialEq)]
+pub struct LineEntry {
+    pub id: String,
+    /// Source block the line is found in
+    pub block: String,
+    pub text: String,
+}
+
+/// All emitted strings within an environment, for handing off to translators
+///
+/// Entries are sorted by block, then by their order in the block, repeated IDs are listed once
+#[derive(Debug,Clone,PartialEq)]
+pub struct StringTable {
+    pub entries: Vec<LineEntry>,
+}
+
+impl StringTable {
+    pub fn new<D> (env: &Env<D>) -> StringTable {
+        let mut names: Vec<&String> = env.src.keys().collect();
+        names.sort();
+
+        let mut entries: Vec<LineEntry> = vec![];
+        for name in names {
+            let b = &env.src[name];
+            for (&(line, idx), id) in b.line_ids.iter() {
+                if entries.iter().any(|e| &e.id == id) { continue }
+
+                let text = b.src.get(line).and_then(src_vars).and_then(|v| v.get(idx));
+                if let Some(&Var::String(ref text)) = text {
+                    entries.push(LineEntry { id: id.clone(), block: name.clone(), text: text.clone() });
+                }
+            }
+        }
+
+        StringTable { entries: entries }
+    }
+
+    /// Comma separated table with a header, columns are id, block, and text
+    pub fn to_csv (&self) -> String {
+        let mut csv = String::from("id,block,text\n");
+        for e in self.entries.iter() {
+            csv.push_str(&format!("{},{},{}\n", csv_field(&e.id), csv_field(&e.block), csv_field(&e.text)));
+        }
+
+        csv
+    }
+
+    /// Gettext style template, IDs are kept as the message context
+    pub fn to_po (&self) -> String {
+        let mut po = String::new();
+        for e in self.entries.iter() {
+            po.push_str(&format!("#: {}\nmsgctxt {}\nmsgid {}\nmsgstr \"\"\n\n",
+                                 e.block, po_string(&e.id), po_string(&e.text)));
+        }
+
+        po
+    }
+}
+
+fn csv_field (s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) { format!("\"{}\"", s.replace('"', "\"\"")) }
+    else { s.to_owned() }
+}
+
+fn po_string (s: &str) -> String {
+    let s = s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    format!("\"{}\"", s)
+}
+
+/// Reads translations from a table in the same format as StringTable::to_csv
+///
+/// Text is read from the last column, so the block column may be left out.
+/// Returns the line number of the first malformed row on failure
+pub fn parse_csv (src: &str) -> Result<HashMap<String, String>, usize> {
+    let mut map = HashMap::new();
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut chars = src.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c == '"' {
+                if chars.peek() == Some(&'"') { chars.next(); field.push('"'); }
+                else { quoted = false; }
+            }
+            else {
+                if c == '\n' { line += 1; }
+                field.push(c);
+            }
+        }
+        else {
+            match c {
+                '"' if field.is_empty() => { quoted = true; },
+                ',' => { row.push(field.clone()); field.clear(); },
+                '\r' => {},
+                '\n' => {
+                    row.push(field.clone());
+                    field.clear();
+                    rows.push((line, row.clone()));
+                    row.clear();
+                    line += 1;
+                },
+                _ => field.push(c),
+            }
+        }
+    }
+
+    if quoted { return Err(line) }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push((line, row));
+    }
+
+    for (i, (line, mut row)) in rows.into_iter().enumerate() {
+        if i == 0 && row.first().map(|s| &s[..]) == Some("id") { continue } // header
+        if row.len() == 1 && row[0].is_empty() { continue }
+        if row.len() < 2 { return Err(line) }
+
+        let text = row.pop().unwrap();
+        map.insert(row.swap_remove(0), text);
+    }
+
+    Ok(map)
+}
@@ -1,7 +1,7 @@
 use var::Var;
-use parse::IR;
-use eval::Evaluator;
-use def::Def;
+use parse::{IR,ParseError,ParseErrorKind};
+use eval::{Eval,Evaluator};
+use expr::Expr;
 
 use std::collections::HashMap;
 
@@ -43,14 +43,30 @@ pub enum Logic {
 
     /// A composite logic type to group logic statements together
     Composite(Expect, Vec<String>),
+
+    /// Comparison expression, with optional arithmetic
+    ///
+    /// eg: hp < max_hp * 0.5, name != "Bob"
+    Expr(Expr),
+
+    /// Random chance of being true, eg: lucky chance 0.25
+    ///
+    /// Rolled by the Evaluator each time the logic is reached
+    Chance(Var),
 }
 
 pub type Logics = HashMap<String,LogicFn>;
-pub struct LogicFn(Box<dyn Fn(&Def,&Logics) -> Option<bool> + Send>);
+#[allow(clippy::type_complexity)]
+pub struct LogicFn(Box<dyn Fn(&dyn Eval,&Logics) -> Option<bool> + Send>);
 impl LogicFn {
-    pub fn run(&self, def: &Def, logic: &Logics) -> Option<bool> {
+    pub fn run(&self, def: &dyn Eval, logic: &Logics) -> Option<bool> {
         self.0(def, logic)
     }
+
+    /// Logic with a fixed result
+    pub fn value(b: bool) -> LogicFn {
+        LogicFn(Box::new(move |_data: &dyn Eval, _logic: &Logics| Some(b)))
+    }
 }
 
 // NOTE: we don't actually impl this, but satisfy checker
@@ -70,7 +86,7 @@ impl fmt::Debug for LogicFn {
 
 impl Logic {
     pub fn parse_comp(mut keys: Vec<&str>,
-                      mut exp: Vec<IR>) -> Result<Logic,&'static str> {
+                      mut exp: Vec<IR>) -> Result<Logic,ParseError> {
         // NOTE: we may want to inspect what happened if the kind was not found
         let kind = Expect::parse(keys.pop().unwrap().to_owned());
 
@@ -79,11 +95,15 @@ impl Logic {
                             exp))
     }
     
-    pub fn parse(mut exp: Vec<IR>) -> Result<Logic,&'static str> {
+    pub fn parse(mut exp: Vec<IR>) -> Result<Logic,ParseError> {
         let len = exp.len();
         
         if len == 1 {
             let mut exp: String = exp.pop().unwrap().into();
+            if exp.is_empty() {
+                return Err(ParseError::new(ParseErrorKind::InvalidLogic).token("\"\""))
+            }
+
             let inv = exp.remove(0);
             if inv == '!' {
                 Ok(Logic::IsNot(exp))
@@ -93,7 +113,14 @@ impl Logic {
                 Ok(Logic::Is(exp))
             }
         }
-        else if len == 3 {
+        else if len == 2 && exp[0] == IR::Sym("chance".to_owned()) {
+            let p = Var::parse(exp.pop().unwrap())?;
+            match p {
+                Var::Num(_) | Var::Int(_) | Var::Sym(_) => Ok(Logic::Chance(p)),
+                _ => Err(ParseError::new(ParseErrorKind::InvalidLogic).token(p.to_string())),
+            }
+        }
+        else if len == 3 && Logic::is_simple(&exp[1]) {
             let var = exp.pop().unwrap();
             let var = Var::parse(var)?;
 
@@ -107,9 +134,31 @@ impl Logic {
             else if sym == "<" {
                 Ok(Logic::LT(key,var))
             }
-            else { Err("Invalid Logic Syntax") }
+            else { Err(ParseError::new(ParseErrorKind::InvalidLogic).token(sym)) }
+        }
+        else if len >= 3 {
+            let e = Expr::parse(exp)?;
+            if e.is_bool() { Ok(Logic::Expr(e)) }
+            else { Err(ParseError::new(ParseErrorKind::InvalidLogic)) }
+        }
+        else {
+            let mut e = ParseError::new(ParseErrorKind::UnbalancedLogic);
+            if let Some(n) = exp.pop() { e = e.token(n); }
+            Err(e)
+        }
+    }
+
+    /// Parses an inline condition, eg: if (gold > 10 and !cursed)
+    pub fn parse_inline(exp: Vec<IR>) -> Result<Logic,ParseError> {
+        Ok(Logic::Expr(Expr::parse(exp)?))
+    }
+
+    /// Simple comparisons are kept as GT/LT
+    fn is_simple (op: &IR) -> bool {
+        match op {
+            &IR::Sym(ref s) => s == ">" || s == "<",
+            _ => false,
         }
-        else { Err("Unbalanced Logic Syntax") }
     }
 
     /// Evaluate Logic into Functions
@@ -117,7 +166,7 @@ impl Logic {
         match self {
             &Logic::Is(ref lookup) => {
                 let lookup = lookup.clone();
-                let lfn = Box::new(move |data: &Def, logic: &Logics| {
+                let lfn = Box::new(move |data: &dyn Eval, logic: &Logics| {
                     if let Some(r) = Evaluator::resolve(&lookup, logic, data) {
                         match r {
                             Var::Bool(v) => {
@@ -135,7 +184,7 @@ impl Logic {
             },
             &Logic::IsNot(ref lookup) => { //inverse state
                 let lookup = lookup.clone();
-                let lfn = Box::new(move |data: &Def, logic: &Logics| {
+                let lfn = Box::new(move |data: &dyn Eval, logic: &Logics| {
                     if let Some(r) = Evaluator::resolve(&lookup, logic, data) {
                         match r {
                             Var::Bool(v) => {
@@ -155,14 +204,14 @@ impl Logic {
             &Logic::GT(ref left, ref right) => {
                 let left = left.clone();
                 let right = right.clone();
-                let lfn = Box::new(move |data: &Def, _logic: &Logics| {
+                let lfn = Box::new(move |data: &dyn Eval, _logic: &Logics| {
                     let right = Var::get_num(&right,data);
                     let left = Var::get_num(&left,data);
                 
-                    if left.is_ok() && right.is_ok() {
-                        Some(left.unwrap() > right.unwrap())
+                    match (left, right) {
+                        (Ok(left), Ok(right)) => Some(left > right),
+                        _ => None,
                     }
-                    else { None }
                 });
 
                 LogicFn(lfn)
@@ -170,21 +219,29 @@ impl Logic {
             &Logic::LT(ref left, ref right) => {
                 let left = left.clone();
                 let right = right.clone();
-                let lfn = Box::new(move |data: &Def, _logic: &Logics| {
+                let lfn = Box::new(move |data: &dyn Eval, _logic: &Logics| {
                     let right = Var::get_num(&right,data);
                     let left = Var::get_num(&left,data);
                     
-                    if left.is_ok() && right.is_ok() {
-                        Some(left.unwrap() < right.unwrap())
+                    match (left, right) {
+                        (Ok(left), Ok(right)) => Some(left < right),
+                        _ => None,
                     }
-                    else { None }
+                });
+
+                LogicFn(lfn)
+            },
+            &Logic::Expr(ref e) => {
+                let e = e.clone();
+                let lfn = Box::new(move |data: &dyn Eval, logic: &Logics| {
+                    e.test(data, logic)
                 });
 
                 LogicFn(lfn)
             },
             &Logic::Composite(x, ref lookups) => {
                 let lookups = lookups.clone();
-                let lfn = Box::new(move |data: &Def, logic: &Logics| {
+                let lfn = Box::new(move |data: &dyn Eval, logic: &Logics| {
                     // track if any lookups are false or true
                     let mut comp_true = false;
                     let mut comp_false = false;
@@ -216,7 +273,13 @@ impl Logic {
                 });
                 
                 LogicFn(lfn)
-            }
+            },
+            &Logic::Chance(_) => {
+                // NOTE: the roll happens in the Evaluator, this is unresolved until then
+                let lfn = Box::new(move |_data: &dyn Eval, _logic: &Logics| None);
+
+                LogicFn(lfn)
+            },
         }
                  
         
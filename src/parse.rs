@@ -1,11 +1,16 @@
-use std::collections::{HashMap,BTreeSet};
+use std::collections::{HashMap,HashSet,BTreeMap,BTreeSet};
 use std::io::prelude::*;
+use std::io;
+use std::str;
 
 use source::Src;
 use var::Var;
-use logic::LogicFn;
+use logic::{Logic,LogicFn};
 use def::DefBlock;
 use env::Env;
+use locale;
+use step::Meta;
+use include::Resolver;
 
 #[derive(Debug,PartialEq)]
 pub struct SrcBlock {
@@ -16,6 +21,23 @@ pub struct SrcBlock {
     pub or_valid: bool,
 
     pub logic: HashMap<String,LogicFn>,
+
+    /// Parameter names bound by next:call, eg: shop_menu merchant qty
+    pub params: Vec<String>,
+    /// Node-local variables, from call arguments and returns
+    pub locals: HashMap<String,Var>,
+    /// Indices of lines which have emitted, readable as 'seen' on that line
+    pub emitted: BTreeSet<usize>,
+    /// Times each sequence line was reached, by line index
+    ///
+    /// Kept across restarts and advances, unlike idx
+    pub seqs: BTreeMap<usize,usize>,
+    /// Last roll of each chance logic, by logic name
+    pub chances: BTreeMap<String,bool>,
+    /// Line IDs of emitted strings, by line index and var index, see locale::StringTable
+    pub line_ids: BTreeMap<(usize,usize),String>,
+    /// Speaker and tags of lines, by line index
+    pub meta: BTreeMap<usize,Meta>,
 }
 
 #[derive(Debug,PartialEq)]
@@ -24,6 +46,23 @@ pub enum Block {
     Def(DefBlock),
 }
 
+impl Block {
+    pub fn name (&self) -> &str {
+        match self {
+            &Block::Src(ref b) => &b.name,
+            &Block::Def(ref b) => &b.name,
+        }
+    }
+
+    /// Blocks are unique by kind and fully qualified name, a def and source block may share a name
+    pub fn key (&self) -> (bool, String) {
+        match self {
+            &Block::Src(ref b) => (false, b.name.clone()),
+            &Block::Def(ref b) => (true, b.name.clone()),
+        }
+    }
+}
+
 /// Intermediate Representation
 ///
 /// This is used during the parsing stage
@@ -34,6 +73,8 @@ pub enum IR {
     Sym(String),
     /// Key-Value, pre-parsed
     Map(Vec<IR>),
+    /// List literal, only built within def blocks
+    Vec(Vec<IR>),
 }
 
 impl From<IR> for String {
@@ -51,7 +92,11 @@ impl From<IR> for String {
                 s.push('}');
 
                 s
-            }
+            },
+            IR::Vec(v) => {
+                let v: Vec<String> = v.into_iter().map(|n| n.into()).collect();
+                format!("[{}]", v.join(" "))
+            },
         }
     }
 }
@@ -65,6 +110,247 @@ impl From<Var> for IR {
 }
 
 
+/// Kinds of failures encountered while parsing
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ParseErrorKind {
+    /// Block terminator found without a block being built
+    BlockNotBuilt,
+    /// Def entry requires a key and a value
+    InvalidDef,
+    /// Or must follow an If
+    OrWithoutIf,
+    /// Statement did not start with a symbol
+    NonSymbol,
+
+    InvalidIf,
+    InvalidOr,
+    InvalidWhen,
+    MissingEmit,
+
+    InvalidLogic,
+    UnbalancedLogic,
+
+    InvalidNext,
+    MissingNext,
+    UnknownTag,
+    InvalidTag,
+    MissingTag,
+
+    InvalidMap,
+    UnbalancedMap,
+
+    /// New requires a symbol to clone from
+    NonSymNew,
+    /// Unknown mutation function
+    UnknownFunction,
+    /// Wrong number of arguments for mutation function
+    InvalidArgs,
+
+    /// Include requires a quoted path
+    InvalidInclude,
+    /// Module requires a single name
+    InvalidModule,
+    /// Included file could not be loaded, or no resolver was given
+    MissingInclude,
+    /// File includes itself, directly or through other files
+    IncludeCycle,
+    /// Block of the same kind and name found twice when merging files
+    DuplicateBlock,
+}
+
+impl ParseErrorKind {
+    pub fn as_str (&self) -> &'static str {
+        match *self {
+            ParseErrorKind::BlockNotBuilt => "Parse Block not built",
+            ParseErrorKind::InvalidDef => "Invalid Def Entry",
+            ParseErrorKind::OrWithoutIf => "If must prepend Or",
+            ParseErrorKind::NonSymbol => "Encountered Non-Symbol Token",
+            ParseErrorKind::InvalidIf => "Invalid IF Logic",
+            ParseErrorKind::InvalidOr => "Invalid OR Logic",
+            ParseErrorKind::InvalidWhen => "Invalid WHEN Logic",
+            ParseErrorKind::MissingEmit => "Missing EMIT Logic",
+            ParseErrorKind::InvalidLogic => "Invalid Logic Syntax",
+            ParseErrorKind::UnbalancedLogic => "Unbalanced Logic Syntax",
+            ParseErrorKind::InvalidNext => "Invalid Next Type Found",
+            ParseErrorKind::MissingNext => "No Next type found",
+            ParseErrorKind::UnknownTag => "Unknown Tag encountered",
+            ParseErrorKind::InvalidTag => "Invalid Tag type",
+            ParseErrorKind::MissingTag => "Missing Tag type",
+            ParseErrorKind::InvalidMap => "Cannot parse map",
+            ParseErrorKind::UnbalancedMap => "Map contains unbalanced braclets",
+            ParseErrorKind::NonSymNew => "Non-Sym found on New",
+            ParseErrorKind::UnknownFunction => "Unimplemented function",
+            ParseErrorKind::InvalidArgs => "Invalid function arguments",
+            ParseErrorKind::InvalidInclude => "Include requires a quoted path",
+            ParseErrorKind::InvalidModule => "Module requires a name",
+            ParseErrorKind::MissingInclude => "Unable to load include",
+            ParseErrorKind::IncludeCycle => "Include cycle found",
+            ParseErrorKind::DuplicateBlock => "Duplicate block name",
+        }
+    }
+}
+
+/// Parse failure, with location in source
+///
+/// Line and column are 1-based, and are 0 when unknown
+#[derive(Debug,Clone,PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// File being parsed, when parsed through includes, see Parser::parse_file
+    pub file: Option<String>,
+    /// Name of the block being built, if any
+    pub block: Option<String>,
+    pub line: usize,
+    pub col: usize,
+    /// Offending token, if known
+    pub token: Option<String>,
+}
+
+impl ParseError {
+    pub fn new (kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            kind: kind,
+            file: None,
+            block: None,
+            line: 0,
+            col: 0,
+            token: None,
+        }
+    }
+
+    /// Attaches the offending token
+    pub fn token<T: Into<String>> (mut self, token: T) -> ParseError {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Attaches the file the error was found in, unless already known
+    pub fn file<T: Into<String>> (mut self, file: T) -> ParseError {
+        if self.file.is_none() { self.file = Some(file.into()); }
+        self
+    }
+
+    /// Locates error within source region, from start to end byte offsets
+    ///
+    /// Points at the offending token when found, otherwise the start of the region
+    pub fn at (mut self, src: &str, start: usize, end: usize, block: Option<&str>) -> ParseError {
+        let mut offset = start;
+        if let Some(ref token) = self.token {
+            if !token.is_empty() {
+                if let Some(region) = src.get(start..end) {
+                    if let Some(i) = region.find(&token[..]) {
+                        offset = start + i;
+                    }
+                }
+            }
+        }
+
+        let (line, col) = location(src, offset);
+        self.line = line;
+        self.col = col;
+        if self.block.is_none() {
+            self.block = block.map(|b| b.to_owned());
+        }
+
+        self
+    }
+}
+
+impl From<ParseErrorKind> for ParseError {
+    fn from(kind: ParseErrorKind) -> ParseError {
+        ParseError::new(kind)
+    }
+}
+
+use std::fmt;
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref file) = self.file {
+            write!(f, "{}:", file)?;
+        }
+        if let Some(ref block) = self.block {
+            write!(f, "{}:", block)?;
+        }
+        write!(f, "{}:{}: {}", self.line, self.col, self.kind.as_str())?;
+        if let Some(ref token) = self.token {
+            write!(f, " `{}`", token)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ::std::error::Error for ParseError {}
+
+/// Line and column (1-based) of byte offset within source
+fn location (src: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(src.len());
+    let before = &src[..offset];
+    let line = before.matches('\n').count() + 1;
+    let col = match before.rfind('\n') {
+        Some(i) => before[i+1..].chars().count() + 1,
+        None => before.chars().count() + 1,
+    };
+
+    (line, col)
+}
+
+/// Result of parsing a single source
+struct Parsed {
+    blocks: Vec<Block>,
+    errors: Vec<ParseError>,
+    /// Include paths, with start and end byte offsets of the directive
+    includes: Vec<(String,usize,usize)>,
+    /// Byte offset of each block's header
+    starts: Vec<usize>,
+    /// Module in effect at the end of the source
+    module: Option<String>,
+}
+
+/// State while parsing files through includes, see Parser::parse_file
+struct Includes {
+    /// Files being parsed, outermost first
+    stack: Vec<String>,
+    loaded: HashSet<String>,
+    /// Block names found so far, see Block::key
+    names: HashSet<(bool,String)>,
+    blocks: Vec<Block>,
+}
+
+impl Includes {
+    fn parse<R: Resolver> (&mut self, path: &str, src: &str, resolver: &mut R) -> Result<(),ParseError> {
+        self.stack.push(path.to_owned());
+        self.loaded.insert(path.to_owned());
+
+        let parsed = Parser::parse(src, false).map_err(|e| e.file(path))?;
+        for (inc, start, end) in parsed.includes {
+            let full = resolver.join(path, &inc);
+            let located = |kind| ParseError::new(kind).token(&inc[..]).at(src, start, end, None).file(path);
+
+            if self.stack.contains(&full) { return Err(located(ParseErrorKind::IncludeCycle)) }
+            if self.loaded.contains(&full) { continue }
+
+            let inc_src = resolver.load(&full).map_err(|_| located(ParseErrorKind::MissingInclude))?;
+            self.parse(&full, &inc_src, resolver)?;
+        }
+
+        for (b, start) in parsed.blocks.into_iter().zip(parsed.starts) {
+            if !self.names.insert(b.key()) {
+                let name = b.name().to_owned();
+                let end = src[start..].find('\n').map(|n| start + n).unwrap_or(src.len());
+                return Err(ParseError::new(ParseErrorKind::DuplicateBlock)
+                           .token(name.rsplit("::").next().unwrap_or(&name))
+                           .at(src, start, end, Some(&name)).file(path))
+            }
+
+            self.blocks.push(b);
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
+}
+
 /// Map object for Selects
 pub type Map = HashMap<String,Vec<Var>>;
 
@@ -77,26 +363,123 @@ impl Deref for Parser {
 }
 
 impl Parser {
-    pub fn parse_blocks (src: &str) -> Result<Parser,&'static str> {
+    pub fn parse_blocks (src: &str) -> Result<Parser,ParseError> {
+        let parsed = Parser::parse(src, false)?;
+        if let Some(&(ref path, start, end)) = parsed.includes.first() {
+            return Err(Parser::unresolved_include(src, path, start, end))
+        }
+
+        Ok(Parser(parsed.blocks))
+    }
+
+    /// Parses a file along with everything it includes, eg: include "town/shop.ls"
+    ///
+    /// Paths are loaded through the resolver, relative to the including file.
+    /// Files included more than once are only parsed the first time,
+    /// blocks of the same kind and name found across files are an error
+    pub fn parse_file<R: Resolver> (path: &str, resolver: &mut R) -> Result<Parser,ParseError> {
+        let src = resolver.load(path).map_err(|_| {
+            ParseError::new(ParseErrorKind::MissingInclude).token(path).file(path)
+        })?;
+
+        let mut includes = Includes { stack: vec![], loaded: HashSet::new(), names: HashSet::new(), blocks: vec![] };
+        includes.parse(path, &src, resolver)?;
+
+        Ok(Parser(includes.blocks))
+    }
+
+    fn unresolved_include (src: &str, path: &str, start: usize, end: usize) -> ParseError {
+        ParseError::new(ParseErrorKind::MissingInclude).token(path).at(src, start, end, None)
+    }
+
+    /// Parses all blocks, recovering from errors
+    ///
+    /// A broken block is skipped up to its terminating semicolon,
+    /// returns cleanly parsed blocks along with all errors found
+    pub fn parse_blocks_recover (src: &str) -> (Parser, Vec<ParseError>) {
+        match Parser::parse(src, true) {
+            Ok(mut parsed) => {
+                for &(ref path, start, end) in parsed.includes.iter() {
+                    parsed.errors.push(Parser::unresolved_include(src, path, start, end));
+                }
+                (Parser(parsed.blocks), parsed.errors)
+            },
+            Err(e) => (Parser(vec![]), vec![e]), // NOTE: recovering parse collects instead
+        }
+    }
+
+    fn parse (src: &str, recover: bool) -> Result<Parsed,ParseError> {
+        Parser::parse_in(src, recover, None)
+    }
+
+    /// Parses with a module already in effect, such as one carried over by StreamParser
+    fn parse_in (src: &str, recover: bool, mut module: Option<String>) -> Result<Parsed,ParseError> {
         let mut v = vec!();
+        let mut errors = vec!();
+        let mut includes = vec!();
+        let mut starts = vec!();
+        let mut block_start = 0;
+        let mut names = HashSet::new();
         let mut exp = String::new();
         let mut exps: Vec<IR> = vec!();
         let mut map_ir: Vec<IR> = vec!(); //contains pre-parsed map
+        let mut list_ir: Vec<IR> = vec!(); //contains def list literal
         let mut block: Option<Block> = None;
 
         let mut in_string = false;
         let mut in_comment = false;
         let mut in_vec = false;
         let mut in_map = false;
+        let mut in_list = false; // vec region within a def block, builds a list
+        let mut in_tags = false; // vec region of line tags, builds a list without ending the statement
         let mut was_if = false;
+        let mut skipping = false; // recovering from a broken block
 
+        // byte offset of the statement currently being built, for error reporting
+        let mut stmt_start: Option<usize> = None;
+        let mut map_start = 0; // byte offset of the opening brace of the current map
         
         let mut usyms = BTreeSet::new(); //unique set, remove dupes
                             
 
-        for c in src.chars() {
+        for (i,c) in src.char_indices() {
+            if skipping { // head to end of the broken block
+                if in_comment { if c == '\n' { in_comment = false; } }
+                else if in_string { if c == '"' { in_string = false; } }
+                else if c == '#' { in_comment = true; }
+                else if c == '"' { in_string = true; }
+                else if c == ';' {
+                    skipping = false;
+                    was_if = false;
+                    usyms.clear();
+                    stmt_start = None;
+                }
+                
+                continue
+            }
+            
+            if stmt_start.is_none() && !in_comment && !c.is_whitespace() && c != '#' {
+                stmt_start = Some(i);
+            }
+            
             if !in_comment && !in_string {
-                if c == '[' { in_vec = true; continue }
+                if c == '[' {
+                    in_vec = true;
+                    in_tags = match block {
+                        Some(Block::Src(_)) => Parser::is_tags(&src[i+1..]),
+                        _ => false,
+                    };
+
+                    if in_tags || matches!(block, Some(Block::Def(_))) {
+                        // push previous symbols
+                        for n in exp.split_whitespace() {
+                            exps.push(IR::Sym(n.trim().to_owned()));
+                        }
+                        exp.clear();
+                        in_list = true;
+                    }
+                    continue
+                }
                 else if c == ']' { in_vec = false; }
                 
             }
@@ -115,6 +498,7 @@ impl Parser {
                 for n in exp.split_whitespace() {
                     let sym = IR::Sym(n.trim().to_owned());
                     if in_map { map_ir.push(sym); }
+                    else if in_list { list_ir.push(sym); }
                     else { exps.push(sym); }
                 }
                 
@@ -123,113 +507,83 @@ impl Parser {
                     exps.push(IR::Map(map_ir));
                     map_ir = vec![];
                 }
+                else if c == ']' && in_list {
+                    in_list = false;
+                    exps.push(IR::Vec(list_ir));
+                    list_ir = vec![];
+
+                    // tags are followed by the rest of the statement
+                    if in_tags {
+                        in_tags = false;
+                        exp = String::new();
+                        continue
+                    }
+                }
                 
                 exp = String::new();
 
-                if exps.len() < 1 { continue }
+                if exps.is_empty() { continue }
                 
                 
-                // determine block type
-                if block.is_none() {
-                    let name = exps.remove(0).into();
-                    if name == "def" {
-                        let b = DefBlock {
-                            name: exps.pop().unwrap().into(),
-                            data: HashMap::new(),
-                        };
-                        
-                        block = Some(Block::Def(b));
-                    }
-                    else {
-                        let b = SrcBlock {
-                            name: name,
-                            src: vec!(),
-                            idx: 0,
-                            visited: false,
-                            or_valid: false,
-                            logic: HashMap::new()
-                        };
-                        
-                        block = Some(Block::Src(b));
-                    }
-                    
-                }
-                else { // build block type
-                    let mut qsyms:Vec<(String,String)> = vec!();
-                    let adjust_sym = |qsyms: &mut Vec<(String,String)>, s: &mut String| {
-                        if s.chars().next() == Some('!') {
-                            let mut sym = "not_".to_owned();
-                            sym.push_str(s[1..].trim());
-                            
-                            let osym = s.trim().to_owned();
-                            
-                            qsyms.push((sym.clone(),osym));
-                            *s = sym;
-                        }
-                    };
-                    
-                    // this builds symbol refs as a convenience
-                    for n in exps.iter_mut() {
-                        match n {
-                            &mut IR::Sym(ref mut s) => {
-                                adjust_sym(&mut qsyms,s);
+                let start = stmt_start.take().unwrap_or(i);
+                
+                let built = match block {
+                    // determine block type
+                    None if exps[0] == IR::Sym("include".to_owned()) => {
+                        match (exps.len(), exps.pop()) {
+                            (2, Some(IR::String(path))) => {
+                                exps.clear();
+                                includes.push((path, start, i));
+                                Ok(())
                             },
-                            &mut IR::Map(ref mut v) => {
-                                for n in v.iter_mut() {
-                                    match n {
-                                        &mut IR::Sym(ref mut s) => {
-                                            adjust_sym(&mut qsyms,s);
-                                        },
-                                        _ => {},
-                                    }
-                                }
+                            _ => Err(ParseError::new(ParseErrorKind::InvalidInclude).token("include")),
+                        }
+                    },
+                    None if exps[0] == IR::Sym("module".to_owned()) => {
+                        match (exps.len(), exps.pop()) {
+                            (2, Some(IR::Sym(name))) if Parser::is_module(&name) => {
+                                exps.clear();
+                                module = Some(name);
+                                Ok(())
                             },
-                            _ => {},
+                            _ => Err(ParseError::new(ParseErrorKind::InvalidModule).token("module")),
                         }
-                    }
-                    
-                    match block {
-                        Some(Block::Def(ref mut b)) => {
-                            let v = exps.pop().unwrap();
-                            let r = Var::parse(v)?;
-                            b.data.insert(exps.pop().unwrap().into(),
-                                         r);
-                        },
-                        Some(Block::Src(ref mut b)) => {
-                            let mut srcs: Vec<Src> = vec![];
-                            
-                            for (qsym,sym) in qsyms.drain(..) {
-                                if usyms.contains(&qsym) { continue }
-                                usyms.insert(qsym.clone());
-                                
-                                let src = Src::parse(vec![IR::Sym(qsym),
-                                                          IR::Sym(sym)])?;
-
-                                srcs.push(src);
-                            }
-
-                            let src = Src::parse(exps)?;
-                            srcs.push(src);
-
-                            for src in srcs.drain(..) {
-                                match &src {
-                                    &Src::If(_,_,_) => { was_if = true; },
-                                    &Src::Or(_,_) => {
-                                        if !was_if {
-                                            return Err("If must prepend Or")
-                                        }
-                                    },
-                                    _ => { was_if = false; },
+                    },
+                    None => {
+                        block_start = start;
+                        Parser::parse_header(&mut exps).map(|mut b| {
+                            if let Some(ref module) = module {
+                                let name = format!("{}::{}", module, b.name());
+                                match b {
+                                    Block::Src(ref mut b) => { b.name = name; },
+                                    Block::Def(ref mut b) => { b.name = name; },
                                 }
-
-                                
-                                b.src.push(src);
                             }
-                        },
-                        _ => {}
-                    }
+                            block = Some(b);
+                        })
+                    },
+                    // build block type
+                    Some(ref mut b) => {
+                        let exps = ::std::mem::take(&mut exps);
+                        Parser::parse_statement(b, exps, &mut usyms, &mut was_if)
+                    },
+                };
 
-                    exps = vec!();
+                if let Err(e) = built {
+                    let e = e.at(src, start, i, block.as_ref().map(|b| b.name()));
+                    if !recover { return Err(e) }
+                    errors.push(e);
+
+                    // drop the broken block entirely
+                    block = None;
+                    skipping = true;
+                    exps.clear();
+                    map_ir.clear();
+                    list_ir.clear();
+                    in_vec = false;
+                    in_map = false;
+                    in_list = false;
+                    in_tags = false;
                 }
             }
             else if c == '"' && !in_comment {
@@ -238,6 +592,7 @@ impl Parser {
                     for n in exp.split_whitespace() {
                         let sym = IR::Sym(n.trim().to_owned());
                         if in_map { map_ir.push(sym); }
+                        else if in_list { list_ir.push(sym); }
                         else { exps.push(sym); }
                     }
                     exp = String::new();
@@ -245,22 +600,67 @@ impl Parser {
                 else if !in_string { //finished the quoted string?
                     let sym = IR::String(exp);
                     if in_map { map_ir.push(sym); }
+                    else if in_list { list_ir.push(sym); }
                     else { exps.push(sym); }
                     exp = String::new();
                 }
             }
             else if c == ';' && !in_string && !in_comment {
+                // maps may span lines, but never the end of a block
+                if in_map {
+                    let e = ParseError::new(ParseErrorKind::UnbalancedMap)
+                        .token("{")
+                        .at(src, map_start, map_start+1, block.as_ref().map(|b| b.name()));
+                    if !recover { return Err(e) }
+                    errors.push(e);
+
+                    // drop the broken block, this is already its end
+                    block = None;
+                    exp.clear();
+                    exps.clear();
+                    map_ir.clear();
+                    list_ir.clear();
+                    in_vec = false;
+                    in_map = false;
+                    in_list = false;
+                    in_tags = false;
+                    was_if = false;
+                    usyms.clear();
+                    stmt_start = None;
+                    continue
+                }
+
                 //fail otherwise, block should be built!
                 if let Some(block_) = block {
-                    v.push(block_);
+                    if !names.insert(block_.key()) {
+                        let name = block_.name().to_owned();
+                        let e = ParseError::new(ParseErrorKind::DuplicateBlock)
+                            .token(name.rsplit("::").next().unwrap_or(&name))
+                            .at(src, block_start, i, Some(&name));
+                        if !recover { return Err(e) }
+                        errors.push(e);
+                    }
+                    else {
+                        v.push(block_);
+                        starts.push(block_start);
+                    }
                     usyms.clear(); //clear out on new block
                     block = None;
+                    stmt_start = None;
+                }
+                else {
+                    let e = ParseError::new(ParseErrorKind::BlockNotBuilt)
+                        .token(";")
+                        .at(src, i, i+1, None);
+                    if !recover { return Err(e) }
+                    errors.push(e);
+                    stmt_start = None;
                 }
-                else { return Err("Parse Block not built")}
             }
             else {
                 if c == '{' && !in_comment && !in_string {
                     in_map = true;
+                    map_start = i;
                     // push previous symbol
                     let sym = IR::Sym(exp.trim().to_owned());
                     exps.push(sym);
@@ -272,14 +672,296 @@ impl Parser {
             }
         }
         
-        Ok(Parser(v))
+        Ok(Parsed { blocks: v, errors: errors, includes: includes, starts: starts, module: module })
+    }
+
+    /// Builds a new block from its header statement
+    fn parse_header (exps: &mut Vec<IR>) -> Result<Block,ParseError> {
+        let name = exps.remove(0).into();
+        if name == "def" {
+            let def_name: String = match exps.pop() {
+                Some(n) => n.into(),
+                None => {
+                    return Err(ParseError::new(ParseErrorKind::InvalidDef)
+                               .token(name))
+                }
+            };
+            
+            let b = DefBlock::new(&def_name);
+            
+            Ok(Block::Def(b))
+        }
+        else {
+            let b = SrcBlock {
+                name: name,
+                src: vec!(),
+                idx: 0,
+                visited: false,
+                or_valid: false,
+                logic: HashMap::new(),
+                params: exps.drain(..).map(|n| n.into()).collect(),
+                locals: HashMap::new(),
+                emitted: BTreeSet::new(),
+                seqs: BTreeMap::new(),
+                chances: BTreeMap::new(),
+                line_ids: BTreeMap::new(),
+                meta: BTreeMap::new(),
+            };
+            
+            Ok(Block::Src(b))
+        }
+    }
+
+    /// Module names may nest, eg: town::blacksmith
+    fn is_module (name: &str) -> bool {
+        !name.is_empty() && name.split("::").all(|n| {
+            !n.is_empty() && !n.contains(['.', ':', '@', '!', '(', ')'])
+        })
+    }
+
+    /// Brackets hold line tags when closed on the same line, with more of the statement following
+    ///
+    /// eg: emit @guard [mood:angry] "Halt!", rather than a multiline region
+    ///
+    /// Tag values may be quoted, eg: [vo:"g 012"]
+    fn is_tags (rest: &str) -> bool {
+        let line = rest.split('\n').next().unwrap_or("");
+        let mut in_string = false;
+        let mut prev = ' ';
+        for (i, c) in line.char_indices() {
+            if in_string { if c == '"' { in_string = false; } }
+            else if c == '"' {
+                if prev != ':' { return false } // quotes only follow a tag key
+                in_string = true;
+            }
+            else if c == ']' {
+                let after = line[i+1..].split('#').next().unwrap_or("");
+                return !after.trim().is_empty()
+            }
+
+            prev = c;
+        }
+
+        false
+    }
+
+    /// Takes the speaker and tags from an emitting statement, eg: emit @guard [mood:angry] "Halt!"
+    ///
+    /// Expected following the head, and the condition of an if
+    fn parse_meta (exps: &mut Vec<IR>) -> Result<Option<Meta>,ParseError> {
+        let idx = match exps.first() {
+            Some(&IR::Sym(ref s)) if s == "if" => 2,
+            Some(&IR::Sym(ref s)) if s == "or" || s == "emit" || s.starts_with("emit:") => 1,
+            _ => return Ok(None),
+        };
+
+        let mut meta = Meta::default();
+        if let Some(&IR::Sym(ref s)) = exps.get(idx) {
+            meta.speaker = s.strip_prefix('@').filter(|n| !n.is_empty()).map(|n| n.to_owned());
+        }
+        if meta.speaker.is_some() { exps.remove(idx); }
+
+        if let Some(&IR::Vec(_)) = exps.get(idx) {
+            if let IR::Vec(tags) = exps.remove(idx) {
+                let mut tags = tags.into_iter().peekable();
+                while let Some(tag) = tags.next() {
+                    match tag {
+                        IR::Sym(t) => {
+                            let (k, v) = t.split_once(':').unwrap_or((&t[..], ""));
+
+                            // quoted values follow their key, eg: vo:"g 012"
+                            let quoted = match tags.peek() {
+                                Some(&IR::String(_)) if t.ends_with(':') => tags.next(),
+                                _ => None,
+                            };
+                            let v = match quoted {
+                                Some(IR::String(s)) => s,
+                                _ => v.to_owned(),
+                            };
+
+                            meta.tags.insert(k.to_owned(), v);
+                        },
+                        _ => return Err(ParseError::new(ParseErrorKind::InvalidTag).token(tag)),
+                    }
+                }
+            }
+        }
+
+        if meta.is_empty() { Ok(None) }
+        else { Ok(Some(meta)) }
+    }
+
+    /// Groups a parenthesized inline condition into a single symbol
+    ///
+    /// Returns the generated symbol name with the condition's tokens
+    fn group_inline (exps: &mut Vec<IR>, idx: usize) -> Option<(String,Vec<IR>)> {
+        match exps.get(idx) {
+            Some(&IR::Sym(ref s)) if s.starts_with('(') => {},
+            _ => return None,
+        }
+
+        let mut depth: isize = 0;
+        let mut end = None;
+        for (i,n) in exps.iter().enumerate().skip(idx) {
+            if let &IR::Sym(ref s) = n {
+                depth += s.matches('(').count() as isize;
+                depth -= s.matches(')').count() as isize;
+            }
+
+            if depth <= 0 { end = Some(i); break }
+        }
+
+        let end = end?;
+        let cond: Vec<IR> = exps.drain(idx..end+1).collect();
+        let name = cond.iter().map(|n| match n {
+            &IR::String(ref s) => format!("{:?}", s),
+            _ => n.clone().into(),
+        }).collect::<Vec<String>>().join(" ");
+        
+        exps.insert(idx, IR::Sym(name.clone()));
+        Some((name,cond))
+    }
+
+    /// Parses a statement into the block being built
+    fn parse_statement (block: &mut Block,
+                        mut exps: Vec<IR>,
+                        usyms: &mut BTreeSet<String>,
+                        was_if: &mut bool) -> Result<(),ParseError> {
+        // inline conditions become their own logic, named after their source
+        let mut inline: Vec<(String,Vec<IR>)> = vec!();
+        let head = match exps.first() {
+            Some(&IR::Sym(ref s)) => s.clone(),
+            _ => String::new(),
+        };
+        
+        if head == "if" {
+            if let Some(cond) = Parser::group_inline(&mut exps, 1) {
+                inline.push(cond);
+            }
+        }
+        else if head == "when" {
+            if let Some(&mut IR::Map(ref mut map)) = exps.get_mut(1) {
+                let mut is_key = true;
+                let mut i = 0;
+                while i < map.len() {
+                    if is_key {
+                        if let Some(cond) = Parser::group_inline(map, i) {
+                            inline.push(cond);
+                        }
+                    }
+                    
+                    is_key = match map[i] {
+                        IR::Sym(ref s) => s.ends_with(','),
+                        _ => false,
+                    };
+                    i += 1;
+                }
+            }
+        }
+        
+        let mut qsyms:Vec<(String,String)> = vec!();
+        let adjust_sym = |qsyms: &mut Vec<(String,String)>, s: &mut String| {
+            if s.starts_with('!') && s != "!=" {
+                // keep trailing map commas and closing parenthesis out of the name
+                let trimmed = s.trim().to_owned();
+                let osym = trimmed.trim_end_matches([',', ')']);
+                
+                let mut sym = "not_".to_owned();
+                sym.push_str(&osym[1..]);
+                
+                qsyms.push((sym.clone(),osym.to_owned()));
+                *s = sym + &trimmed[osym.len()..];
+            }
+        };
+        
+        // this builds symbol refs as a convenience
+        for n in exps.iter_mut() {
+            match n {
+                &mut IR::Sym(ref mut s) => {
+                    adjust_sym(&mut qsyms,s);
+                },
+                &mut IR::Map(ref mut v) => {
+                    for n in v.iter_mut() {
+                        if let &mut IR::Sym(ref mut s) = n {
+                            adjust_sym(&mut qsyms,s);
+                        }
+                    }
+                },
+                _ => {},
+            }
+        }
+        
+        match block {
+            &mut Block::Def(ref mut b) => {
+                if exps.len() < 2 {
+                    let mut e = ParseError::new(ParseErrorKind::InvalidDef);
+                    if let Some(n) = exps.pop() { e = e.token(n); }
+                    return Err(e)
+                }
+                
+                let v = exps.pop().unwrap();
+                let r = Var::parse(v)?;
+                b.data.insert(exps.pop().unwrap().into(),
+                             r);
+            },
+            &mut Block::Src(ref mut b) => {
+                let mut srcs: Vec<Src> = vec![];
+                
+                for (qsym,sym) in qsyms.drain(..) {
+                    if usyms.contains(&qsym) { continue }
+                    usyms.insert(qsym.clone());
+                    
+                    let src = Src::parse(vec![IR::Sym(qsym),
+                                              IR::Sym(sym)])?;
+
+                    srcs.push(src);
+                }
+
+                for (name,cond) in inline.drain(..) {
+                    if usyms.contains(&name) { continue }
+                    usyms.insert(name.clone());
+
+                    let logic = Logic::parse_inline(cond)?;
+                    srcs.push(Src::Logic(name, logic));
+                }
+
+                let meta = Parser::parse_meta(&mut exps)?;
+                let src = Src::parse(exps)?;
+                srcs.push(src);
+
+                for mut src in srcs.drain(..) {
+                    match &src {
+                        &Src::If(_,_,_) => { *was_if = true; },
+                        &Src::Or(_,_) => {
+                            if !*was_if {
+                                return Err(ParseError::new(ParseErrorKind::OrWithoutIf)
+                                           .token("or"))
+                            }
+                        },
+                        _ => { *was_if = false; },
+                    }
+
+                    for (i, id) in locale::assign_ids(&b.name, &mut src) {
+                        b.line_ids.insert((b.src.len(), i), id);
+                    }
+                    b.src.push(src);
+                }
+
+                // metadata belongs to the statement, pushed last
+                if let Some(meta) = meta {
+                    b.meta.insert(b.src.len() - 1, meta);
+                }
+            },
+        }
+
+        Ok(())
     }
 
     /// Consumes parser, pushes blocks onto existing vec
     ///
     /// Returns starting index of where it was pushed onto vec
     pub fn sink (mut self, v: &mut Vec<Block>) -> Option<usize> {
-        if self.0.len() > 0 {
+        if !self.0.is_empty() {
             let start = Some(v.len());
             for b in self.0.drain(..) {
                 v.push(b);
@@ -298,10 +980,15 @@ impl Parser {
         env
     }
 
+    /// Consumes parser, for inserting into an existing environment, see Env::try_insert
+    pub fn into_blocks (self) -> Vec<Block> {
+        self.0
+    }
+
     /// Parses a map from IR
     ///
     /// Parsed using commas for variable sized maps
-    pub fn parse_map (map_ir: IR) -> Result<Map,&'static str> {
+    pub fn parse_map (map_ir: IR) -> Result<Map,ParseError> {
         let mut map: Map = HashMap::new(); // optionally unbounded val-lengths
 
         match map_ir {
@@ -314,10 +1001,12 @@ impl Parser {
 
                     match n {
                         IR::Sym(mut s) => {
-                            if s.chars().last() == Some(',') {
+                            if s.ends_with(',') {
                                 let _ = s.pop();
-                                let var = Var::parse(IR::Sym(s))?;
-                                vals.push(var);
+                                if !s.is_empty() { // comma may trail a quoted string
+                                    let var = Var::parse(IR::Sym(s))?;
+                                    vals.push(var);
+                                }
 
                                 map.insert(key,vals);
                                 vals = vec![];
@@ -333,35 +1022,79 @@ impl Parser {
                     }
                 }
 
-                if !key.is_empty() && vals.len() > 0 {
+                if !key.is_empty() && !vals.is_empty() {
                     map.insert(key,vals);
                 }
                 else if !key.is_empty() {
-                    return Err("Map contains unbalanced braclets")
+                    return Err(ParseError::new(ParseErrorKind::UnbalancedMap).token(key))
                 }
                 
                 
                 
                 Ok(map)
             },
-            _=> { return Err("Map type not found") }
+            _=> { Err(ParseError::new(ParseErrorKind::InvalidMap).token(map_ir)) }
         }
     }
 }
 
 
 
+/// Failures reading a stream, see StreamParser::try_parse
+///
+/// Blocks which fail to parse are queued instead, see StreamParser::take_errors
+#[derive(Debug)]
+pub enum StreamError {
+    /// Reading from the stream failed
+    Io(io::Error),
+    /// Stream is not valid UTF-8, at a byte offset from the start of the stream
+    Utf8(usize),
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &StreamError::Io(ref e) => write!(f, "unable to read stream: {}", e),
+            &StreamError::Utf8(offset) => write!(f, "invalid UTF-8 at byte {}", offset),
+        }
+    }
+}
+
+impl ::std::error::Error for StreamError {}
+
 pub struct StreamParser<S:Read> {
+    /// Bytes read but not yet decoded, such as a character split between reads
+    bytes: Vec<u8>,
+    /// Bytes decoded so far, for locating invalid UTF-8
+    decoded: usize,
     /// The non-parsed leftovers of a stream that is being buffered actively
     buf: String,
     pub stream: S,
     size: usize,
     pub blocks: Vec<Block>,
     curr_block: String,
+
+    // quoting state at the end of the buffer, so semicolons within are skipped over
+    in_string: bool,
+    in_comment: bool,
+    /// Line and column the buffer starts at within the stream
+    line: usize,
+    col: usize,
+    /// Module in effect for following blocks
+    module: Option<String>,
+    /// Block names parsed so far, see Block::key
+    names: HashSet<(bool,String)>,
+    /// Blocks which failed to parse, located within the stream
+    errors: Vec<ParseError>,
+    error: Option<StreamError>,
 }
 
 impl<S:Read> Iterator for StreamParser<S> {
     type Item=usize;
+
+    /// Ends iteration at the end of the stream, or when reading fails, see StreamParser::error
+    ///
+    /// Blocks failing to parse do not end iteration, see StreamParser::take_errors
     fn next(&mut self) -> Option<Self::Item> {
         self.parse()
     }
@@ -373,66 +1106,154 @@ impl<S:Read> StreamParser<S> {
     /// Optionally specify chunk size on buffering
     pub fn new (s: S, size: Option<usize>) -> StreamParser<S> {
         StreamParser {
+            bytes: vec![],
+            decoded: 0,
             buf: String::new(),
             stream: s,
             blocks: vec![],
-            size: { if let Some(size) = size { size }
+            size: { if let Some(size) = size { size.max(1) }
                     else { 1024 } },
             curr_block: "".to_owned(),
+            in_string: false,
+            in_comment: false,
+            line: 1,
+            col: 1,
+            module: None,
+            names: HashSet::new(),
+            errors: vec![],
+            error: None,
         }
     }
 
     /// Moves parsed blocks into existing environment
+    ///
+    /// Fails with the header of a block still being buffered
     pub fn sink (&mut self, v: &mut Env) -> Result<(),&str> {
         if !self.curr_block.is_empty() { return Err(&self.curr_block) }
             
-        for b in self.blocks.drain(..) {
-            match b {
-                Block::Src(b) => { v.src.insert(b.name.clone(), b); },
-                Block::Def(b) => { v.def.insert(b.name.clone(), b); },
-            }
-        }
+        v.insert(self.blocks.drain(..).collect());
 
         Ok(())
     }
 
-    /// Parses blocks from stream, returns the index of the new starting block
+    /// Parses blocks from stream, returns the index of the first new block
+    ///
+    /// Returns None at the end of the stream, or when reading fails, see StreamParser::error
     pub fn parse (&mut self) -> Option<usize> {
+        match self.try_parse() {
+            Ok(r) => r,
+            Err(e) => { self.error = Some(e); None },
+        }
+    }
+
+    /// Last read failure from StreamParser::parse, if any
+    pub fn error (&self) -> Option<&StreamError> {
+        self.error.as_ref()
+    }
+
+    /// Takes the errors of blocks which failed to parse so far
+    pub fn take_errors (&mut self) -> Vec<ParseError> {
+        ::std::mem::take(&mut self.errors)
+    }
+
+    /// Reads from stream until at least one block is parsed, returns the index of the first new block
+    ///
+    /// Every block from that index on is new. Returns None at the end of the stream, anything
+    /// left over is kept for when more source arrives, eg: after swapping in a new stream.
+    /// A block failing to parse is skipped and its error queued, see StreamParser::take_errors
+    pub fn try_parse (&mut self) -> Result<Option<usize>, StreamError> {
         let mut buf = vec![0u8;self.size];
-        if let Ok(n) = self.stream.read(&mut buf[..]) {
-            if n > 0 {
-                let _ = buf.truncate(n);
-                if let Ok(s) = String::from_utf8(buf) {
-                    self.buf.push_str(&s);
-
-                    let mut block = String::new();
-                    let mut start = None;
-                    for c in self.buf.drain(..) {
-                        block.push(c);
-                        if self.curr_block.is_empty() && c == '\n' {
-                            self.curr_block = block.clone();
-                        }
-                        
-                        if c == ';' {
-                            if let Ok(p) = Parser::parse_blocks(&block) {
-                                start = p.sink(&mut self.blocks);
-                                self.curr_block.clear();
-                            }
-                            else { return None } //end iteration when parsing fails
-                            
-                            block.clear();
-                        }
+        loop {
+            let n = match self.stream.read(&mut buf[..]) {
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(StreamError::Io(e)),
+            };
+            if n == 0 { return Ok(None) }
+
+            self.bytes.extend_from_slice(&buf[..n]);
+            let valid = match str::from_utf8(&self.bytes) {
+                Ok(s) => s.len(),
+                // character split between reads, finished by the next one
+                Err(ref e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(e) => return Err(StreamError::Utf8(self.decoded + e.valid_up_to())),
+            };
+
+            let s: String = str::from_utf8(&self.bytes[..valid]).unwrap_or_default().to_owned();
+            self.bytes.drain(..valid);
+            self.decoded += valid;
+
+            if let Some(start) = self.scan(&s) { return Ok(Some(start)) }
+        }
+    }
+
+    /// Buffers source, parsing each block as its terminating semicolon is found
+    fn scan (&mut self, s: &str) -> Option<usize> {
+        let mut start = None;
+
+        for c in s.chars() {
+            self.buf.push(c);
+            if self.in_comment {
+                if c == '\n' { self.in_comment = false; }
+                continue
+            }
+
+            match c {
+                '"' => { self.in_string = !self.in_string; },
+                '#' if !self.in_string => { self.in_comment = true; },
+                ';' if !self.in_string => {
+                    let block = ::std::mem::take(&mut self.buf);
+                    match self.parse_block(&block) {
+                        Ok(idx) => { start = start.or(idx); },
+                        Err(e) => { self.errors.push(e); },
                     }
 
-                    if !block.is_empty() { self.buf = block; } //put back anything if needed
-                    
-                    return start
-                }
+                    for c in block.chars() {
+                        if c == '\n' { self.line += 1; self.col = 1; }
+                        else { self.col += 1; }
+                    }
+                },
+                _ => {},
             }
         }
 
-        
-        None
+        // header of the block being buffered, if any
+        self.curr_block = self.buf.lines()
+            .map(|l| l.split('#').next().unwrap_or("").trim())
+            .find(|l| !l.is_empty())
+            .unwrap_or("").to_owned();
+
+        start
+    }
+
+    fn parse_block (&mut self, src: &str) -> Result<Option<usize>, ParseError> {
+        let parsed = Parser::parse_in(src, false, self.module.clone()).map_err(|e| self.locate(e))?;
+        if let Some(&(ref path, start, end)) = parsed.includes.first() {
+            return Err(self.locate(Parser::unresolved_include(src, path, start, end)))
+        }
+
+        for (b, &start) in parsed.blocks.iter().zip(parsed.starts.iter()) {
+            if self.names.contains(&b.key()) {
+                let name = b.name().to_owned();
+                let end = src[start..].find('\n').map(|n| start + n).unwrap_or(src.len());
+                return Err(self.locate(ParseError::new(ParseErrorKind::DuplicateBlock)
+                                       .token(name.rsplit("::").next().unwrap_or(&name))
+                                       .at(src, start, end, Some(&name))))
+            }
+        }
+
+        self.module = parsed.module;
+        self.names.extend(parsed.blocks.iter().map(|b| b.key()));
+        Ok(Parser(parsed.blocks).sink(&mut self.blocks))
     }
 
+    /// Moves an error's location from within the buffer to within the stream
+    fn locate (&self, mut e: ParseError) -> ParseError {
+        if e.line > 0 {
+            if e.line == 1 { e.col += self.col - 1; }
+            e.line += self.line - 1;
+        }
+
+        e
+    }
 }
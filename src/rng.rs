@@ -0,0 +1,74 @@
+use std::time::{SystemTime,UNIX_EPOCH};
+
+/// Random source used by the Evaluator, for random nodes and chance logic
+///
+/// Implement this to supply your own generator, see Evaluator::set_rng
+pub trait Rng: Send {
+    /// Uniform float within [0,1)
+    fn next_f32 (&mut self) -> f32;
+
+    /// State to resume from when restoring a snapshot, as a XorShift seed
+    ///
+    /// Generators without one are seeded from the clock on restore
+    fn state (&self) -> Option<u64> { None }
+}
+
+/// Default xorshift generator, seedable for deterministic tests and replays
+#[derive(Debug,Clone)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    pub fn new (seed: u64) -> XorShift {
+        // zero state never changes, so nudge it
+        let state = if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed };
+        XorShift { state: state }
+    }
+
+    /// Seeds from the system clock
+    pub fn from_time () -> XorShift {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() ^ (d.subsec_nanos() as u64) << 32)
+            .unwrap_or(0);
+
+        XorShift::new(seed)
+    }
+
+    pub fn next_u64 (&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+impl Rng for XorShift {
+    fn next_f32 (&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn state (&self) -> Option<u64> {
+        Some(self.state)
+    }
+}
+
+/// Picks from weighted entries, non-positive weights are never picked
+///
+/// Returns None when there is nothing to pick from
+pub fn choose<'a> (rng: &mut dyn Rng, weights: &'a [(String, f32)]) -> Option<&'a str> {
+    let total: f32 = weights.iter().filter(|w| w.1 > 0.).map(|w| w.1).sum();
+    if total <= 0. { return None }
+
+    let mut r = rng.next_f32() * total;
+    let mut last = None;
+    for &(ref name, w) in weights.iter().filter(|w| w.1 > 0.) {
+        if r < w { return Some(name) }
+        r -= w;
+        last = Some(&name[..]);
+    }
+
+    last // float rounding may leave a remainder
+}
@@ -0,0 +1,107 @@
+use std::collections::{HashMap,BTreeMap,BTreeSet};
+
+use env::Env;
+use def::Def;
+use eval::EvaluatorState;
+use source::Src;
+use var::Var;
+use logic::LogicFn;
+
+/// Current snapshot format version
+///
+/// Bumped whenever the format changes. Older snapshots are still restored,
+/// fields added since then take their defaults, newer versions are refused
+pub const VERSION: u32 = 3;
+
+/// Mutable state of a source block
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NodeState {
+    pub idx: usize,
+    pub visited: bool,
+    pub or_valid: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub locals: HashMap<String, Var>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub visits: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub emitted: BTreeSet<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub seqs: BTreeMap<usize, usize>,
+    /// Added in version 2
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub chances: BTreeMap<String, bool>,
+}
+
+/// Snapshot of all mutable runtime state
+///
+/// Logic and source are not included, these are rebuilt by parsing the same source again.
+/// With the serde feature enabled this can be serialized for save games
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Snapshot {
+    pub version: u32,
+    pub def: Def,
+    pub nodes: HashMap<String, NodeState>,
+    pub eval: EvaluatorState,
+}
+
+impl Snapshot {
+    pub fn new (env: &Env, node_stack: &[String]) -> Snapshot {
+        let nodes = env.src.iter().map(|(name, b)| {
+            (name.clone(), NodeState { idx: b.idx,
+                                       visited: b.visited,
+                                       or_valid: b.or_valid,
+                                       locals: b.locals.clone(),
+                                       visits: env.visits(name),
+                                       emitted: b.emitted.clone(),
+                                       seqs: b.seqs.clone(),
+                                       chances: b.chances.clone() })
+        }).collect();
+
+        Snapshot {
+            version: VERSION,
+            def: env.def.clone(),
+            nodes: nodes,
+            eval: EvaluatorState::new(node_stack.to_vec()),
+        }
+    }
+
+    /// Restores state onto an environment, typically freshly parsed
+    ///
+    /// Returns the evaluator state to resume from, see EvaluatorState::to_eval
+    /// Nodes missing from the environment are skipped
+    pub fn restore (self, env: &mut Env) -> Result<EvaluatorState, &'static str> {
+        if self.version > VERSION { return Err("Unsupported snapshot version") }
+
+        env.def = self.def;
+        
+        for (name, state) in self.nodes {
+            if let Some(b) = env.src.get_mut(&name) {
+                b.idx = state.idx.min(b.src.len());
+                b.visited = state.visited;
+                b.or_valid = state.or_valid;
+                b.locals = state.locals;
+                b.emitted = state.emitted;
+                b.seqs = state.seqs;
+                b.chances = state.chances;
+                env.visits.insert(name.clone(), state.visits);
+
+                // logic is compiled when reached, rebuild what was passed
+                b.logic.clear();
+                for src in b.src[..b.idx].iter() {
+                    if let &Src::Logic(ref name, ref logic) = src {
+                        // chances keep what was rolled
+                        let lfn = match b.chances.get(name) {
+                            Some(&hit) => LogicFn::value(hit),
+                            None => logic.eval(),
+                        };
+                        b.logic.insert(name.clone(), lfn);
+                    }
+                }
+            }
+        }
+
+        Ok(self.eval)
+    }
+}
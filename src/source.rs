@@ -3,9 +3,9 @@ use std::collections::HashMap;
 use logic::{Logic,LogicFn};
 use eval::{Eval,Evaluator};
 use var::{Var,Mut};
-use parse::{Parser,Map,IR};
-use def::Def;
+use parse::{Parser,Map,IR,ParseError,ParseErrorKind};
 use fun::Fun;
+use expr::{Op,Expr};
 
 /// Source block statement types
 #[derive(Debug,PartialEq)]
@@ -28,6 +28,12 @@ pub enum Src {
     /// Just emits variables
     Emit(Vec<Var>), 
 
+    /// Emits one variable from a sequence each time it's reached
+    ///
+    /// eg: emit:cycle "Hi" "Hello" "Hey"
+    /// Picked by the Evaluator, which tracks progress per line in the source block
+    Seq(Seq, Vec<Var>),
+
     /// Ends execution and begins next node
     Next(Next),
 
@@ -41,11 +47,49 @@ pub enum Src {
     When(WhenMap),
 }
 
+/// Sequence kinds for varying emits
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum Seq {
+    /// Each in order, then nothing
+    Once,
+    /// Each in order, looping back to the first
+    Cycle,
+    /// Each in order, sticking on the last
+    Stopping,
+    /// Any picked at random each time
+    Shuffle,
+}
+
+impl Seq {
+    pub fn parse (tag: &str) -> Option<Seq> {
+        match tag {
+            "once" => Some(Seq::Once),
+            "cycle" => Some(Seq::Cycle),
+            "stopping" => Some(Seq::Stopping),
+            "shuffle" => Some(Seq::Shuffle),
+            _ => None,
+        }
+    }
+
+    /// Index to emit, given the times reached before and a roll for shuffle
+    pub fn pick (&self, count: usize, len: usize, roll: f32) -> Option<usize> {
+        if len == 0 { return None }
+
+        match *self {
+            Seq::Once => if count < len { Some(count) } else { None },
+            Seq::Cycle => Some(count % len),
+            Seq::Stopping => Some(count.min(len - 1)),
+            Seq::Shuffle => Some(((roll * len as f32) as usize).min(len - 1)),
+        }
+    }
+}
+
 /// Internal type to hold a specialized When-Mutate Map
 pub type WhenMap = HashMap<String,(Mut,String,Vec<Var>)>;
 
 /// Next-node action types
 #[derive(Debug,PartialEq,Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Next {
     /// Instantly advances
     Now(String),
@@ -63,24 +107,99 @@ pub enum Next {
     Await(String),
 
     /// Select from a group, based on decision
+    ///
+    /// Entries may be guarded by logic or an inline condition following the node,
+    /// eg: next:select {"[Bribe]" bribe if (player.gold > 10), "Leave" exit}
     Select(Map),
 
     /// Calls a node, pushes it onto stack
-    Call(String),
+    ///
+    /// Arguments are bound to the node's parameters as node-local variables,
+    /// eg: next:call shop_menu merchant_id 3
+    Call(String, Vec<Var>),
+
+    /// Heads back to the calling node, writing the value into its locals under the called node's name
+    ///
+    /// eg: return total
+    Return(Option<Var>),
+
+    /// Advances to a node picked at random, by weight, eg: next:random {node_a 3, node_b 1}
+    ///
+    /// Entries are sorted by node name
+    Random(Vec<(String,f32)>),
 
     /// Exits evaluation completely
     Exit
 }
 impl Next {
-    pub fn parse(exp: &mut Vec<IR>) -> Result<Next,&'static str> {
+    /// Names of nodes headed to, for resolving module names
+    pub fn nodes_mut (&mut self) -> Vec<&mut String> {
+        match self {
+            &mut Next::Now(ref mut nn) | &mut Next::Await(ref mut nn) |
+            &mut Next::Restart(Some(ref mut nn)) | &mut Next::Call(ref mut nn, _) => vec![nn],
+            &mut Next::Select(ref mut map) => {
+                map.values_mut().filter_map(|vals| match vals.get_mut(0) {
+                    Some(&mut Var::Sym(ref mut nn)) => Some(nn),
+                    _ => None,
+                }).collect()
+            },
+            &mut Next::Random(ref mut nodes) => nodes.iter_mut().map(|n| &mut n.0).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Guard of a select entry, the condition following 'if'
+    pub fn guard(vals: &[Var]) -> Result<Option<Expr>,ParseError> {
+        match vals.get(1) {
+            Some(&Var::Sym(ref s)) if s == "if" => {},
+            _ => return Ok(None),
+        }
+
+        if vals.len() < 3 {
+            return Err(ParseError::new(ParseErrorKind::InvalidLogic).token("if"))
+        }
+
+        let exp = vals[2..].iter().map(|v| match v {
+            &Var::String(ref s) => IR::String(s.clone()),
+            _ => IR::Sym(v.to_string()),
+        }).collect();
+
+        Expr::parse(exp).map(Some)
+    }
+
+    fn parse_random(map_ir: IR) -> Result<Next,ParseError> {
+        let map = Parser::parse_map(map_ir)?;
+        let mut weights = vec![];
+        for (node, vals) in map {
+            let w = match vals.first() {
+                None => 1.,
+                Some(&Var::Num(n)) => n,
+                Some(&Var::Int(n)) => n as f32,
+                Some(v) => {
+                    return Err(ParseError::new(ParseErrorKind::InvalidNext)
+                               .token(v.to_string()))
+                },
+            };
+
+            weights.push((node, w));
+        }
+
+        if weights.is_empty() {
+            return Err(ParseError::new(ParseErrorKind::InvalidNext).token("next:random"))
+        }
+
+        // maps are unordered, keep picks deterministic for a seed
+        weights.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(Next::Random(weights))
+    }
+
+    pub fn parse(exp: &mut Vec<IR>) -> Result<Next,ParseError> {
         let mut select_idx = None;
         for (i,n) in exp.iter().enumerate() {
             match n {
-                &IR::Sym(ref s) => {
-                    if s == &"next:select" {
-                        select_idx = Some(i);
-                        break
-                    }
+                &IR::Sym(ref s) if s == "next:select" => {
+                    select_idx = Some(i);
+                    break
                 },
                 _ => {},
             }
@@ -89,12 +208,27 @@ impl Next {
         
         // handle nested selects as a special case
         if let Some(idx) = select_idx {
+            if exp.len() < idx + 2 {
+                return Err(ParseError::new(ParseErrorKind::InvalidNext).token("next:select"))
+            }
+
             let map_ir = exp.remove(idx+1);
             let _ = exp.remove(idx); // next:select statement
-            if let Ok(map) = Parser::parse_map(map_ir) {
-                return Ok(Next::Select(map))
+            let map = Parser::parse_map(map_ir)?;
+            for vals in map.values() { let _ = Next::guard(vals)?; } // fail early on bad guards
+            return Ok(Next::Select(map))
+        }
+
+        // calls may have trailing arguments
+        let call_idx = exp.iter().position(|n| n == &IR::Sym("next:call".to_owned()));
+        if let Some(idx) = call_idx {
+            if exp.len() > idx + 2 {
+                let mut args = vec![];
+                for n in exp.drain(idx+2..) { args.push(Var::parse(n)?); }
+                let node = exp.remove(idx+1);
+                let _ = exp.remove(idx); // next:call statement
+                return Ok(Next::Call(node.into(), args))
             }
-            else { return Err("Cannot parse map") }
         }
         
 
@@ -112,30 +246,32 @@ impl Next {
                                 Some("now") => { next = Next::Now(node.into()) },
                                 Some("await") => { next = Next::Await(node.into()) },
                                 Some("restart") => { next = Next::Restart(Some(node.into())) },
-                                Some("call") => { next = Next::Call(node.into()) },
-                                _ => { return Err("Invalid Next Type Found") },
+                                Some("call") => { next = Next::Call(node.into(), vec![]) },
+                                Some("random") => { next = Next::parse_random(node)? },
+                                _ => {
+                                    return Err(ParseError::new(ParseErrorKind::InvalidNext)
+                                               .token(tag.clone()))
+                                },
                             }
                         }
-                        else if next_tag.next().is_some() {
-                            return Err("Unknown Tag encountered")
-                        }
-                        else {
+                        else { // other tags, such as line IDs, are left for the caller
                             exp.push(IR::Sym(tag.to_owned()));
                             exp.push(node);
-                            return Err("Invalid Tag type")
+                            return Err(ParseError::new(ParseErrorKind::InvalidTag)
+                                       .token(tag))
                         }
                     },
                     _ => {
                         exp.push(tag);
                         exp.push(node);
-                        return Err("Invalid Tag type")
+                        return Err(ParseError::new(ParseErrorKind::InvalidTag))
                     }
                 }
             }
             else { // NOTE: this are next commands without node names
                 match node {
                     IR::Sym(ref tag) => {
-                        let tag: &str = &tag;
+                        let tag: &str = tag;
                         match tag {
                             "next:back" => { next = Next::Back },
                             "next:restart" => { next = Next::Restart(None) },
@@ -143,18 +279,19 @@ impl Next {
                             "next:clear" => { next = Next::Clear },
                             _ => {
                                 exp.push(IR::Sym(tag.to_owned()));
-                                return Err("Invalid Tag type")
+                                return Err(ParseError::new(ParseErrorKind::InvalidTag)
+                                           .token(tag))
                             },
                         }
                     },
                     _ => {
                         exp.push(node);
-                        return Err("Missing Tag type")
+                        return Err(ParseError::new(ParseErrorKind::MissingTag))
                     }
                 }
             }
         }
-        else { return Err("No Next type found") }
+        else { return Err(ParseError::new(ParseErrorKind::MissingNext)) }
 
         Ok(next)
     }
@@ -162,9 +299,19 @@ impl Next {
 
 
 impl Src {
+    /// Next node action of a statement, if any
+    pub fn next_mut (&mut self) -> Option<&mut Next> {
+        match self {
+            &mut Src::Next(ref mut next) |
+            &mut Src::If(_, _, Some(ref mut next)) |
+            &mut Src::Or(_, Some(ref mut next)) => Some(next),
+            _ => None,
+        }
+    }
+
     pub fn eval (&self,
                  logic: &mut HashMap<String,LogicFn>,
-                 def: &mut Def,
+                 def: &mut dyn Eval,
                  fun: &mut HashMap<String,Fun>)
                  -> (Vec<Var>,Option<Next>)
     {
@@ -172,36 +319,69 @@ impl Src {
             &Src::Mut(ref m, ref v, ref a) => {
                 match m {
                     &Mut::Add | &Mut::Sub | &Mut::Mul | &Mut::Div => {
-                        let mut num = None;
+                        let op = match m {
+                            &Mut::Add => Op::Add,
+                            &Mut::Sub => Op::Sub,
+                            &Mut::Mul => Op::Mul,
+                            _ => Op::Div,
+                        };
 
                         let var_name = Var::Sym(v.to_owned());
-                        let v1 = Var::get_num(&var_name, def);
-                        
-                        if let Ok(v1) = v1 {
-                            let var_name = &a[0];
-                            let v2 = Var::get_num(&var_name, def);
-                            
-                            if let Ok(v2) = v2 {
-                                match m {
-                                    &Mut::Add => {
-                                        num = Some(v1+v2);
+                        let v1 = Var::get_number(&var_name, def);
+                        let v2 = Var::get_number(&a[0], def);
+
+                        if let (Ok(v1), Ok(v2)) = (v1, v2) {
+                            if let Some(num) = op.apply(&v1, &v2) {
+                                def.set_path(v, num);
+                            }
+                        }
+                    },
+                    &Mut::Push | &Mut::Remove | &Mut::Contains => {
+                        // resolve symbols when possible, otherwise keep them as is
+                        let args: Vec<Var> = a.iter().map(|n| match n {
+                            &Var::Sym(ref s) => {
+                                Evaluator::resolve(s, logic, def).unwrap_or_else(|| n.clone())
+                            },
+                            _ => n.clone(),
+                        }).collect();
+
+                        match m {
+                            &Mut::Push => {
+                                match def.get_path(v) {
+                                    Some(Var::List(mut l)) => {
+                                        l.extend(args);
+                                        def.set_path(v, Var::List(l));
                                     },
-                                    &Mut::Sub => {
-                                        num = Some(v1-v2);
+                                    Some(Var::Map(mut map)) if args.len() == 2 => {
+                                        map.insert(args[0].to_string(), args[1].clone());
+                                        def.set_path(v, Var::Map(map));
                                     },
-                                    &Mut::Mul => {
-                                        num = Some(v1*v2);
+                                    None => { def.set_path(v, Var::List(args)); },
+                                    _ => {}, // NOTE: we do not convert other var types
+                                }
+                            },
+                            &Mut::Remove => {
+                                match def.get_path(v) {
+                                    Some(Var::List(mut l)) => {
+                                        for n in args.iter() {
+                                            if let Some(i) = l.iter().position(|x| x == n) {
+                                                l.remove(i);
+                                            }
+                                        }
+                                        def.set_path(v, Var::List(l));
                                     },
-                                    &Mut::Div => {
-                                        num = Some(v1/v2);
+                                    Some(Var::Map(mut map)) => {
+                                        for n in args.iter() { map.remove(&n.to_string()); }
+                                        def.set_path(v, Var::Map(map));
                                     },
                                     _ => {},
                                 }
-                            }
-                        }
-                        
-                        if let Some(num) = num {
-                            def.set_path(&v, Var::Num(num));
+                            },
+                            _ => {
+                                if let Some(r) = args[0].contains(&args[1]) {
+                                    def.set_path(v, Var::Bool(r));
+                                }
+                            },
                         }
                     },
                     &Mut::Swap => {
@@ -209,19 +389,8 @@ impl Src {
                         def.set_path(v,val); // NOTE: this will also build a var from scratch
                     },
                     &Mut::New => {
-                        match a[0] {
-                            Var::Sym(ref sym) => {
-                                let mut block = None;
-                                if let Some(b) = def.get(sym) {
-                                    block = Some(b.clone());
-                                }
-
-                                if let Some(block) = block {
-                                    def.insert(v.to_string(), block);
-                                }
-                            },
-                            _ => { } // We do nothing with other var types
-                        }
+                        // we do nothing with other var types
+                        if let Var::Sym(ref sym) = a[0] { def.clone_path(v, sym); }
                     }
                     &Mut::Fn(ref fun_name) => {
                         // NOTE: currently we skip non-resolved symbols!
@@ -229,7 +398,7 @@ impl Src {
                         for n in a {
                             match n {
                                 &Var::Sym(ref n) => {
-                                    if let Some(v) = Evaluator::resolve(n, &logic, &def) {
+                                    if let Some(v) = Evaluator::resolve(n, logic, def) {
                                         args.push(v)
                                     }
                                 },
@@ -237,24 +406,32 @@ impl Src {
                             }
                         }
 
-                        if let Some(mfn) = fun.get_mut(fun_name) {
-                            if let Some(r) = mfn.run(&args, def) {
-                                def.set_path(&v, r);
-                            }
+                        // registered functions take precedence over the store's own
+                        let r = match fun.get_mut(fun_name) {
+                            Some(mfn) => mfn.run(&args, def),
+                            None => def.call(Var::Sym(v.to_owned()), fun_name, &args),
+                        };
+
+                        if let Some(r) = r {
+                            def.set_path(v, r);
                         }
                     },
                 }
                 
-                return (vec![],None)
+                (vec![],None)
             }
             &Src::Next(ref next) => {
-                return (vec![],Some(next.clone()))
+                (vec![],Some(next.clone()))
             },
             &Src::Or(ref vars, ref next) => {
-                return (vars.clone(), next.clone())
+                (vars.clone(), next.clone())
             },
             &Src::Emit(ref vars) => {
-                return (vars.clone(),None)
+                (vars.clone(),None)
+            },
+            &Src::Seq(_,_) => {
+                // NOTE: picked in the Evaluator, which holds the sequence state
+                (vec![],None)
             },
             &Src::Logic(ref name, ref logic_src)=> {
                 // NOTE: we only add logicfn if not compiled yet!
@@ -263,7 +440,7 @@ impl Src {
                     logic.insert(name.clone(),lfn);
                 }
                 
-                return (vec![],None) // logic does not return anything
+                (vec![],None) // logic does not return anything
             },
             &Src::If(ref lookup, ref v, ref next) => {
                 let mut is_true = false;
@@ -275,8 +452,8 @@ impl Src {
                     }
                 }
                 
-                if is_true { return ((*v).clone(), next.clone()) }
-                else { return (vec![],None) }
+                if is_true { ((*v).clone(), next.clone()) }
+                else { (vec![],None) }
             },
             &Src::When(ref map) => {
                 for (k, &(ref m, ref v, ref a)) in map.iter() {
@@ -296,22 +473,24 @@ impl Src {
                     }
                 }
                 
-                return (vec![],None)
+                (vec![],None)
             },
         }
     }
     
-    pub fn parse(mut exp: Vec<IR>) -> Result<Src,&'static str> {
+    pub fn parse(mut exp: Vec<IR>) -> Result<Src,ParseError> {
         let ir = exp.remove(0);
         match ir {
             IR::Sym(ref sym) => {
-                if sym.chars().next() == Some('@') { //mutating statement
+                if sym.starts_with('@') { //mutating statement
                     exp.insert(0,IR::Sym(sym.to_owned()));
                     let (m, v, a) = Mut::parse(&mut exp)?;
-                    return Ok(Src::Mut(m,v,a))
+                    Ok(Src::Mut(m,v,a))
                 }
                 else if sym == "when" {
-                    if exp.len() != 1 { return Err("Invalid WHEN Logic") }
+                    if exp.len() != 1 {
+                        return Err(ParseError::new(ParseErrorKind::InvalidWhen).token("when"))
+                    }
                     if let Ok(mut map) = Parser::parse_map(exp.pop().unwrap()) {
                         let mut when_map: WhenMap = HashMap::new();
                         for (k,mut v) in map.drain() {
@@ -321,17 +500,24 @@ impl Src {
                                 Src::Mut(m,v,a) => {
                                     when_map.insert(k, (m,v,a));
                                 },
-                                _ => { return Err("Invalid WHEN Logic"); }
+                                _ => {
+                                    return Err(ParseError::new(ParseErrorKind::InvalidWhen)
+                                               .token(k))
+                                }
                             }
                         }
 
-                        if when_map.is_empty() { return Err("Unable to parse WHEN Map into Mut") }
+                        if when_map.is_empty() {
+                            return Err(ParseError::new(ParseErrorKind::InvalidWhen).token("when"))
+                        }
                         Ok(Src::When(when_map))
                     }
-                    else { Err(" Invalid WHEN Logic") }
+                    else { Err(ParseError::new(ParseErrorKind::InvalidWhen).token("when")) }
                 }
                 else if sym == "if" {
-                    if exp.len() < 2 { return Err("Invalid IF Logic") }
+                    if exp.len() < 2 {
+                        return Err(ParseError::new(ParseErrorKind::InvalidIf).token("if"))
+                    }
 
                     let x = exp.remove(0);
                     let next = Next::parse(&mut exp);
@@ -346,7 +532,9 @@ impl Src {
                                v, next.ok()))
                 }
                 else if sym == "or" {
-                    if exp.len() < 1 { return Err("Invalid OR Logic") }
+                    if exp.is_empty() {
+                        return Err(ParseError::new(ParseErrorKind::InvalidOr).token("or"))
+                    }
 
                     let next = Next::parse(&mut exp);
                     
@@ -358,16 +546,43 @@ impl Src {
                     
                     Ok(Src::Or(v,next.ok()))
                 }
-                else if &sym.split_terminator(':').next() == &Some("next") {
+                else if sym.split_terminator(':').next() == Some("next") {
                     exp.insert(0, IR::Sym(sym.to_owned()));
-                    let next = Next::parse(&mut exp);
-                    if let Ok(next) = next {
-                        Ok(Src::Next(next))
+                    let next = Next::parse(&mut exp)?;
+                    Ok(Src::Next(next))
+                }
+                else if sym == "return" {
+                    if exp.len() > 1 {
+                        return Err(ParseError::new(ParseErrorKind::InvalidNext).token("return"))
                     }
-                    else { Err("Invalid NEXT Logic") }
+
+                    let v = match exp.pop() {
+                        Some(n) => Some(Var::parse(n)?),
+                        None => None,
+                    };
+
+                    Ok(Src::Next(Next::Return(v)))
+                }
+                else if let Some(tag) = sym.strip_prefix("emit:") {
+                    let seq = match Seq::parse(tag) {
+                        Some(seq) => seq,
+                        None => {
+                            return Err(ParseError::new(ParseErrorKind::UnknownTag)
+                                       .token(sym.to_owned()))
+                        }
+                    };
+
+                    if exp.is_empty() {
+                        return Err(ParseError::new(ParseErrorKind::MissingEmit).token(sym.to_owned()))
+                    }
+
+                    let mut v = vec![];
+                    for e in exp.drain(..) { v.push(Var::parse(e)?); }
+
+                    Ok(Src::Seq(seq, v))
                 }
                 else if sym == "emit" {
-                    if exp.len() > 0 {
+                    if !exp.is_empty() {
                         let mut v = vec![];
                         for e in exp.drain(..) {
                             let r = Var::parse(e)?;
@@ -376,7 +591,7 @@ impl Src {
 
                         Ok(Src::Emit(v))
                     }
-                    else { Err("Missing EMIT Logic") }
+                    else { Err(ParseError::new(ParseErrorKind::MissingEmit).token("emit")) }
                 }
                 else {
                     let mut keys: Vec<&str> = sym.split_terminator(':').collect();
@@ -393,7 +608,7 @@ impl Src {
                     }
                 }
             },
-            _ => { Err("Encountered Non-Symbol Token") },
+            _ => { Err(ParseError::new(ParseErrorKind::NonSymbol).token(ir)) },
         }
     }
 }
@@ -0,0 +1,54 @@
+use std::collections::BTreeMap;
+
+use var::Var;
+use parse::Map;
+
+/// Speaker and tags attached to a line, eg: emit @guard [mood:angry vo:g_012] "Halt!"
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct Meta {
+    pub speaker: Option<String>,
+    /// Tags without a value, eg: [whisper], map to an empty string
+    pub tags: BTreeMap<String,String>,
+}
+
+impl Meta {
+    pub fn is_empty (&self) -> bool {
+        self.speaker.is_none() && self.tags.is_empty()
+    }
+}
+
+/// An option within a select, see Evaluator::choose
+#[derive(Debug,Clone,PartialEq)]
+pub struct Choice {
+    /// Key to choose with, typically the text shown
+    pub key: String,
+    /// Node advanced to when chosen
+    pub node: String,
+    /// Guard passed, disabled choices cannot be chosen, see Evaluator::show_disabled
+    pub enabled: bool,
+}
+
+impl Choice {
+    /// Builds choices from a select map, sorted by key
+    pub fn from_map (map: &Map) -> Vec<Choice> {
+        let mut options: Vec<Choice> = map.iter().filter_map(|(key, vals)| {
+            vals.first().map(|node| Choice { key: key.clone(), node: node.to_string(), enabled: true })
+        }).collect();
+        options.sort_by(|a, b| a.key.cmp(&b.key));
+
+        options
+    }
+}
+
+/// Result of stepping the Evaluator, see Evaluator::step
+#[derive(Debug,Clone,PartialEq)]
+pub enum Step {
+    /// Emitted variables, with the line's speaker and tags
+    Line { vars: Vec<Var>, meta: Meta },
+    /// Awaits a choice, see Evaluator::choose, otherwise the current node continues
+    Choice { options: Vec<Choice> },
+    /// Awaits manual advancement to node, otherwise the current node continues
+    Await { node: String },
+    /// Nothing left to evaluate
+    Finished,
+}
@@ -1,25 +1,43 @@
 use eval::Eval;
-use parse::IR;
-use def::Def;
+use parse::{IR,Parser,ParseError,ParseErrorKind};
+
+use std::collections::BTreeMap;
+use std::fmt;
 
 /// Supported Var Types
 ///
 /// These are parsed from IR variants
 #[derive(Debug,Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Var {
     String(String),
     Num(f32),
+    /// Whole number, suffixed with i, eg: 100i
+    Int(i64),
     Bool(bool),
     Sym(String),
+    /// List literal in def blocks, eg: ["sword" "shield"]
+    List(Vec<Var>),
+    /// Map literal in def blocks, eg: {main "started", side "none"}
+    Map(BTreeMap<String,Var>),
 }
 
-impl ToString for Var {
-    fn to_string(&self) -> String {
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &Var::String(ref s) => s.clone(),
-            &Var::Sym(ref s) => s.clone(),
-            &Var::Num(ref n) => n.to_string(),
-            &Var::Bool(ref b) => b.to_string(),
+            &Var::String(ref s) => write!(f, "{}", s),
+            &Var::Sym(ref s) => write!(f, "{}", s),
+            &Var::Num(ref n) => write!(f, "{}", n),
+            &Var::Int(ref n) => write!(f, "{}", n),
+            &Var::Bool(ref b) => write!(f, "{}", b),
+            &Var::List(ref v) => {
+                let v: Vec<String> = v.iter().map(|n| n.to_string()).collect();
+                write!(f, "[{}]", v.join(", "))
+            },
+            &Var::Map(ref m) => {
+                let v: Vec<String> = m.iter().map(|(k,n)| format!("{}: {}", k, n)).collect();
+                write!(f, "{{{}}}", v.join(", "))
+            },
         }
     }
 }
@@ -34,36 +52,101 @@ impl From<f32> for Var {
         Var::Num(t)
     }
 }
+impl From<i64> for Var {
+    fn from(t:i64) -> Var {
+        Var::Int(t)
+    }
+}
+impl From<Vec<Var>> for Var {
+    fn from(t:Vec<Var>) -> Var {
+        Var::List(t)
+    }
+}
 impl From<String> for Var {
     fn from(t:String) -> Var {
         Var::String(t)
     }
 }
-impl<'a> From<&'a str> for Var {
+impl From<&str> for Var {
     fn from(t:&str) -> Var {
         Var::String(t.to_owned())
     }
 }
 
 impl Var {
-    pub fn parse(t: IR) -> Result<Var,&'static str> {
+    pub fn parse(t: IR) -> Result<Var,ParseError> {
         match t {
             IR::Sym(t) => {
                 if let Ok(v) = t.parse::<f32>() {
                     Ok(Var::Num(v))
                 }
+                else if let Some(v) = Var::parse_int(&t) {
+                    Ok(Var::Int(v))
+                }
                 else if let Ok(v) = t.parse::<bool>() {
                     Ok(Var::Bool(v))
                 }
                 else { Ok(Var::Sym(t)) }
             },
             IR::String(s) => { Ok(Var::String(s)) },
-            _ => { Err("No Var type represents a Map") },
+            IR::Vec(mut v) => {
+                let mut list = vec![];
+                for n in v.drain(..) { list.push(Var::parse(n)?); }
+                Ok(Var::List(list))
+            },
+            IR::Map(_) => {
+                let mut map = BTreeMap::new();
+                for (k, mut v) in Parser::parse_map(t)? {
+                    let v = if v.len() == 1 { v.pop().unwrap() }
+                    else { Var::List(v) };
+                    map.insert(k, v);
+                }
+
+                Ok(Var::Map(map))
+            },
+        }
+    }
+
+    fn parse_int (t: &str) -> Option<i64> {
+        t.strip_suffix('i').and_then(|n| n.parse::<i64>().ok())
+    }
+
+    /// Get any underlying number, keeping integers whole
+    pub fn get_number (&self, data: &dyn Eval) -> Result<Var,&'static str> {
+        match self {
+            &Var::Num(_) | &Var::Int(_) => Ok(self.clone()),
+            &Var::Sym(ref s) => {
+                match data.get_last(s) {
+                    Some((n @ Var::Num(_), _)) | Some((n @ Var::Int(_), _)) => Ok(n),
+                    Some(_) => Err("ERROR: NaN Evaluation"),
+                    None => Err("ERROR: Empty Evaluation"),
+                }
+            },
+            _ => Err("ERROR: NaN Evaluation"),
+        }
+    }
+
+    /// Underlying number of a literal, integers are widened
+    pub fn as_num (&self) -> Option<f64> {
+        match self {
+            &Var::Num(n) => Some(n as f64),
+            &Var::Int(n) => Some(n as f64),
+            _ => None,
+        }
+    }
+
+    /// Tests if list contains item, map contains key, or string contains substring
+    pub fn contains (&self, item: &Var) -> Option<bool> {
+        match self {
+            &Var::List(ref v) => Some(v.contains(item)),
+            &Var::Map(ref m) => Some(m.contains_key(&item.to_string())),
+            &Var::String(ref s) => Some(s.contains(&item.to_string()[..])),
+            _ => None,
         }
     }
 
     /// Get any underlying number
-    pub fn get_num (&self, data: &Def) -> Result<f32,&'static str> {
+    pub fn get_num (&self, data: &dyn Eval) -> Result<f32,&'static str> {
         let num;
         match self {
             &Var::Num(n) => { num = n; },
@@ -71,15 +154,17 @@ impl Var {
                 if let Some((n,_res)) = data.get_last(s) {
                     match n {
                         Var::Num(n) => { num = n; },
+                        Var::Int(n) => { num = n as f32; },
                         _ => return Err("ERROR: NaN Evaluation")
                     }
                 }
                 else {  return Err("ERROR: Empty Evaluation") }
             },
+            &Var::Int(n) => { num = n as f32; },
             _ =>  return Err("ERROR: NaN Evaluation")
         }
 
-        return Ok(num)
+        Ok(num)
     }
 }
 
@@ -95,6 +180,13 @@ pub enum Mut {
     /// Swaps value
     Swap,
 
+    /// Appends to a list, or inserts key and value into a map
+    Push,
+    /// Removes item from a list, or key from a map
+    Remove,
+    /// Writes if a list, map, or string contains an item
+    Contains,
+
     /// Custom function reference
     Fn(String),
 }
@@ -102,19 +194,13 @@ pub enum Mut {
 
 impl Mut {
     /// Parses in a custom function, symbol must be surrounded by (parenthesis)
-    pub fn parse_fn (mut exp: String) -> Option<String> {
-        if exp.chars().next() == Some('(') {
-            let _ = exp.remove(0);
-            let close = exp.pop().unwrap();
-            if close == ')' {
-                return Some(exp)
-            }
-        }
-        
-        None
+    pub fn parse_fn (exp: String) -> Option<String> {
+        exp.strip_prefix('(')
+            .and_then(|s| s.strip_suffix(')'))
+            .map(|s| s.to_owned())
     }
     
-    pub fn parse(exps: &mut Vec<IR>) -> Result<(Mut, String, Vec<Var>), &'static str> {
+    pub fn parse(exps: &mut Vec<IR>) -> Result<(Mut, String, Vec<Var>), ParseError> {
         let m;
         let mut v: String;
         let mut a = vec![];
@@ -134,13 +220,25 @@ impl Mut {
                 "-" => { m = Mut::Sub },
                 "*" => { m = Mut::Mul },
                 "/" => { m = Mut::Div },
+                "push" => { m = Mut::Push },
+                "remove" => { m = Mut::Remove },
+                "contains" => {
+                    m = Mut::Contains;
+                    if a.len() != 2 {
+                        return Err(ParseError::new(ParseErrorKind::InvalidArgs)
+                                   .token(x))
+                    }
+                },
                 "new" => {
                     m = Mut::New;
                     
                     // fail on non-sym values
                     match a[0] {
                         Var::Sym(_) => {},
-                        _ => { return Err("Non-Sym found on New") }
+                        _ => {
+                            return Err(ParseError::new(ParseErrorKind::NonSymNew)
+                                       .token(a[0].to_string()))
+                        }
                     }
                 },
                 _ => {
@@ -148,11 +246,16 @@ impl Mut {
                         m = Mut::Fn(fun)
                     }
                     else {
-                        return Err("Unimplemented function")
+                        return Err(ParseError::new(ParseErrorKind::UnknownFunction)
+                                   .token(x))
                     }
                 }
             }
         }
+        else if exps.len() < 2 {
+            let token: String = exps.pop().map(|n| n.into()).unwrap_or_default();
+            return Err(ParseError::new(ParseErrorKind::InvalidArgs).token(token))
+        }
         else {
             let r = Var::parse(exps.pop().unwrap())?;
             a.push(r);
@@ -0,0 +1,60 @@
+extern crate lichen;
+#[macro_use] extern crate lichen_derive;
+
+use lichen::parse::Parser;
+use lichen::var::Var;
+use lichen::eval::Evaluator;
+use lichen::def::LichenDef;
+
+#[derive(Debug,PartialEq,LichenDef)]
+struct Bag {
+    coins: i64,
+    rope: bool,
+}
+
+#[derive(Debug,PartialEq,LichenDef)]
+struct Player {
+    name: String,
+    hp: f32,
+    bag: Bag,
+}
+
+#[test]
+fn derive_def_roundtrip() {
+    let player = Player { name: "Bob".to_owned(), hp: 10.,
+                          bag: Bag { coins: 5, rope: true } };
+
+    let block = player.to_def("player");
+    assert_eq!(block.data["name"], "Bob".into());
+    assert_eq!(block.blocks["bag"].data["coins"], Var::Int(5));
+    assert_eq!(Player::from_def(&block), Some(player));
+
+    let mut block = block;
+    block.data.remove("hp");
+    assert_eq!(Player::from_def(&block), None);
+}
+
+#[test]
+fn derive_def_env() {
+    let src = "root\n
+    @player.hp - 2.5\n
+    @player.bag.coins + 1i\n
+    @player.bag.rope false\n
+    emit player.name player.bag.coins\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let player = Player { name: "Bob".to_owned(), hp: 10.,
+                          bag: Bag { coins: 5, rope: true } };
+    env.insert_def("player", &player);
+
+    {
+        let mut ev = Evaluator::new(&mut env);
+        let (vars,_) = ev.next().unwrap();
+        assert_eq!(vars, vec!["Bob".into(), Var::Int(6)]);
+    }
+
+    let player: Player = env.get_def("player").expect("ERROR: Player not found");
+    assert_eq!(player, Player { name: "Bob".to_owned(), hp: 7.5,
+                                bag: Bag { coins: 6, rope: false } });
+}
@@ -0,0 +1,102 @@
+extern crate lichen;
+
+use lichen::parse::Parser;
+use lichen::eval::{Evaluator,EvalError};
+use lichen::format::{self,Unresolved,Spec};
+use lichen::lint::Warning;
+use lichen::var::Var;
+
+fn lines (src: &str, policy: Unresolved) -> Result<Vec<Var>, EvalError> {
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+    ev.set_unresolved(policy);
+
+    let mut vars = vec![];
+    while let Some((v, _)) = ev.try_next()? { vars.extend(v); }
+    Ok(vars)
+}
+
+const SRC: &str = "root\n
+    @player.name \"Io\"\n
+    @player.gold 5.5\n
+    @player.keys 1i\n
+    emit \"{player.name}'s sword\"\n
+    emit \"You have {player.gold:.0} gold.\"\n
+    emit \"[{player.name:>4}|{player.gold:<6.2}]\"\n
+    emit \"{player.keys|# key|# keys}, {player.gold:.1|# coin|# coins}\"\n
+    emit \"{{literal}} {visited:root}\"\n
+;";
+
+#[test]
+fn format_fields() {
+    assert_eq!(lines(SRC, Unresolved::Keep).unwrap(),
+               vec!["Io's sword".into(),
+                    "You have 6 gold.".into(),
+                    "[  Io|5.50  ]".into(),
+                    "1 key, 5.5 coins".into(),
+                    "{literal} true".into()]);
+}
+
+#[test]
+fn format_unresolved() {
+    let src = "root\n
+    emit \"Hello {player.nickname}!\"\n
+    emit \"after\"\n
+;";
+
+    assert_eq!(lines(src, Unresolved::Keep).unwrap(),
+               vec!["Hello {player.nickname}!".into(), "after".into()]);
+    assert_eq!(lines(src, Unresolved::Empty).unwrap(),
+               vec!["Hello !".into(), "after".into()]);
+    assert_eq!(lines(src, Unresolved::Error),
+               Err(EvalError::Unresolved { node: "root".to_owned(),
+                                           symbol: "player.nickname".to_owned() }));
+
+    let env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    assert_eq!(env.validate(), vec![Warning::UndefinedDef { block: "root".to_owned(),
+                                                            path: "player.nickname".to_owned() }]);
+}
+
+#[test]
+fn format_pieces() {
+    let f = |s: &str| format::interpolate(s, Unresolved::Keep, |sym| {
+        if sym == "n" { Some(Var::Int(3)) } else { None }
+    }).unwrap();
+
+    assert_eq!(f("{n}{n:03}"), "3  3"); // width only, no zero fill
+    assert_eq!(f("{n:^5}|"), "  3  |");
+    assert_eq!(f("unclosed {n"), "unclosed {n");
+    assert_eq!(f("}}{{n}}"), "}{n}");
+    assert_eq!(f("{n|#th}"), "3th");
+
+    assert_eq!(Spec::parse(">8.2"), Some(Spec { align: Some('>'), width: 8, precision: Some(2) }));
+    assert_eq!(Spec::parse("intro"), None);
+    assert_eq!(Spec::parse("."), None);
+}
+
+#[test]
+fn format_empty_fields() {
+    let src = "root\n
+    @p.gold 1i\n
+    emit \"json: {}\"\n
+    emit \"x { } y\"\n
+    emit \"price {:>5}\"\n
+    emit \"{} {p.gold}\"\n
+;";
+
+    assert_eq!(lines(src, Unresolved::Error).unwrap(),
+               vec!["json: {}".into(), "x { } y".into(), "price {:>5}".into(), "{} 1".into()]);
+    assert_eq!(lichen::eval::as_path(""), (None, ""));
+}
+
+#[test]
+fn format_values_not_rescanned() {
+    let src = "root\n
+    @p.name \"`p.gold\"\n
+    @p.gold 1i\n
+    emit \"{p.name}'s sword, `p.gold coin\"\n
+;";
+
+    assert_eq!(lines(src, Unresolved::Keep).unwrap(),
+               vec!["`p.gold's sword, 1 coin".into()]);
+}
@@ -0,0 +1,153 @@
+extern crate lichen;
+
+use std::fs;
+
+use lichen::parse::{Parser,ParseErrorKind};
+use lichen::include::{self,MemResolver,FileResolver};
+use lichen::eval::Evaluator;
+use lichen::var::Var;
+
+fn resolver () -> MemResolver {
+    let mut r = MemResolver::new();
+    r.insert("main.ls", "include \"town/shop.ls\"\n
+include \"common.ls\"\n
+root\n
+    emit \"in town\"\n
+    next:call shop\n
+    next:call farewell\n
+;");
+    r.insert("town/shop.ls", "# shops share the farewell\n
+include \"../common.ls\"\n
+shop\n
+    emit \"in shop\"\n
+;");
+    r.insert("common.ls", "farewell\n
+    emit \"bye\"\n
+;");
+    r
+}
+
+#[test]
+fn include_files() {
+    let mut r = resolver();
+    let mut env = Parser::parse_file("main.ls", &mut r).expect("ERROR: Unable to parse source").into_env();
+    assert!(env.validate().is_empty());
+
+    let ev = Evaluator::new(&mut env);
+    let vars: Vec<Var> = ev.flat_map(|(vars, _)| vars).collect();
+    assert_eq!(vars, vec!["in town".into(), "in shop".into(), "bye".into()]);
+}
+
+#[test]
+fn include_cycle() {
+    let mut r = resolver();
+    r.insert("common.ls", "\ninclude \"main.ls\"\n");
+
+    let err = Parser::parse_file("main.ls", &mut r).err().expect("ERROR: Invalid source parsed");
+    assert_eq!(err.kind, ParseErrorKind::IncludeCycle);
+    assert_eq!(err.file, Some("common.ls".to_owned()));
+    assert_eq!((err.line, err.col), (2, 10));
+}
+
+#[test]
+fn include_missing() {
+    let mut r = resolver();
+    r.files.remove("common.ls");
+
+    let err = Parser::parse_file("main.ls", &mut r).err().expect("ERROR: Invalid source parsed");
+    assert_eq!(err.kind, ParseErrorKind::MissingInclude);
+    assert_eq!(err.file, Some("town/shop.ls".to_owned()));
+    assert_eq!(err.token, Some("../common.ls".to_owned()));
+    assert_eq!(err.line, 3);
+
+    let err = Parser::parse_file("nowhere.ls", &mut r).err().expect("ERROR: Invalid source parsed");
+    assert_eq!(err.kind, ParseErrorKind::MissingInclude);
+
+    // without a resolver includes cannot be loaded
+    let err = Parser::parse_blocks("include \"common.ls\"\nroot\n    emit \"hi\"\n;").err()
+        .expect("ERROR: Invalid source parsed");
+    assert_eq!(err.kind, ParseErrorKind::MissingInclude);
+    assert_eq!((err.line, err.col), (1, 10));
+}
+
+#[test]
+fn include_errors_located() {
+    let mut r = resolver();
+    r.insert("common.ls", "farewell\n    heavy weight >> 5\n;");
+
+    let err = Parser::parse_file("main.ls", &mut r).err().expect("ERROR: Invalid source parsed");
+    assert_eq!(err.kind, ParseErrorKind::InvalidLogic);
+    assert_eq!(err.file, Some("common.ls".to_owned()));
+    assert_eq!((err.line, err.col), (2, 18));
+    assert!(err.to_string().starts_with("common.ls:farewell:2:18:"));
+}
+
+#[test]
+fn include_duplicate_blocks() {
+    let mut r = resolver();
+    r.insert("common.ls", "farewell\n    emit \"bye\"\n;\nshop\n    emit \"other shop\"\n;\ndef shop\n    open true\n;");
+
+    let err = Parser::parse_file("main.ls", &mut r).err().expect("ERROR: Invalid source parsed");
+    assert_eq!(err.kind, ParseErrorKind::DuplicateBlock);
+    assert_eq!(err.file, Some("town/shop.ls".to_owned()));
+    assert_eq!(err.block, Some("shop".to_owned()));
+    assert_eq!(err.line, 5);
+
+    // def and source blocks may share a name
+    r.insert("common.ls", "farewell\n    emit \"bye\"\n;\ndef shop\n    open true\n;");
+    assert!(Parser::parse_file("main.ls", &mut r).is_ok());
+}
+
+#[test]
+fn include_join() {
+    assert_eq!(include::join("main.ls", "town/shop.ls"), "town/shop.ls");
+    assert_eq!(include::join("town/square.ls", "./shop.ls"), "town/shop.ls");
+    assert_eq!(include::join("town/square.ls", "../shop.ls"), "shop.ls");
+    assert_eq!(include::join("main.ls", "../shared/a.ls"), "../shared/a.ls");
+    assert_eq!(include::join("town/square.ls", "/abs/a.ls"), "/abs/a.ls");
+}
+
+#[test]
+fn include_from_disk() {
+    let dir = std::env::temp_dir().join(format!("lichen_include_{}", std::process::id()));
+    fs::create_dir_all(dir.join("town")).unwrap();
+    fs::write(dir.join("main.ls"), "include \"town/shop.ls\"\nroot\n    next:now shop\n;").unwrap();
+    fs::write(dir.join("town/shop.ls"), "shop\n    emit \"in shop\"\n;").unwrap();
+
+    let mut r = FileResolver::new(&dir);
+    let parsed = Parser::parse_file("main.ls", &mut r);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let mut env = parsed.expect("ERROR: Unable to parse source").into_env();
+    let ev = Evaluator::new(&mut env);
+    assert_eq!(ev.last().unwrap().0, vec![Var::from("in shop")]);
+}
+
+#[test]
+fn include_outside_root() {
+    let base = std::env::temp_dir().join(format!("lichen_include_root_{}", std::process::id()));
+    let dir = base.join("scripts");
+    fs::create_dir_all(dir.join("town")).unwrap();
+    fs::write(base.join("secret.ls"), "secret\n    emit \"hidden\"\n;").unwrap();
+    fs::write(dir.join("town/shop.ls"), "shop\n    emit \"in shop\"\n;").unwrap();
+
+    let r = FileResolver::new(&dir);
+    let inside = r.resolve("town/../town/./shop.ls").map(|p| p.ends_with("town/shop.ls"));
+    let parent = r.resolve("../secret.ls").map_err(|e| e.kind());
+    let absolute = r.resolve(&base.join("secret.ls").to_string_lossy()).map_err(|e| e.kind());
+
+    let mut r = FileResolver::new(&dir);
+    let mut parse = |src: &str| {
+        fs::write(dir.join("main.ls"), src).unwrap();
+        Parser::parse_file("main.ls", &mut r).err().map(|e| e.kind)
+    };
+    let escaped = parse("include \"../secret.ls\"\nroot\n    emit \"hi\"\n;");
+    let nested = parse("include \"town/../../secret.ls\"\nroot\n    emit \"hi\"\n;");
+    fs::remove_dir_all(&base).unwrap();
+
+    assert!(inside.unwrap());
+    assert_eq!(parent, Err(std::io::ErrorKind::PermissionDenied));
+    assert_eq!(absolute, Err(std::io::ErrorKind::PermissionDenied));
+    assert_eq!(escaped, Some(ParseErrorKind::MissingInclude));
+    assert_eq!(nested, Some(ParseErrorKind::MissingInclude));
+}
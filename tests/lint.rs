@@ -0,0 +1,66 @@
+extern crate lichen;
+
+use lichen::parse::Parser;
+use lichen::lint::Warning;
+
+#[test]
+fn validate_env() {
+    let src = "root\n
+    has_coins global.coins > 0\n
+    if has_coins \"rich\" next:now store\n
+    if has_gold \"richer\"\n
+    emit \"hi `global.nam\"\n
+    next:select {\"Leave?\" exit-town,\n
+                \"Stay?\" root}\n
+;\n
+store\n
+    @player.bag new bag\n
+    emit player.bag.size\n
+    next:call nowhere\n
+;\n
+hidden\n
+    emit \"never seen\"\n
+;\n
+def global\n
+    coins 1\n
+    name \"my-game\"\n
+;\n
+def bag\n
+    size 5\n
+;";
+
+    let env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let warnings = env.validate();
+
+    assert!(warnings.contains(&Warning::UndefinedLogic { block: "root".to_owned(),
+                                                        logic: "has_gold".to_owned() }));
+    assert!(warnings.contains(&Warning::UndefinedDef { block: "root".to_owned(),
+                                                      path: "global.nam".to_owned() }));
+    assert!(warnings.contains(&Warning::DanglingNode { block: "root".to_owned(),
+                                                      node: "exit-town".to_owned() }));
+    assert!(warnings.contains(&Warning::DanglingNode { block: "store".to_owned(),
+                                                      node: "nowhere".to_owned() }));
+    assert!(warnings.contains(&Warning::Unreachable { block: "hidden".to_owned() }));
+    assert!(warnings.contains(&Warning::UnusedDef { block: "global".to_owned(),
+                                                   key: "name".to_owned() }));
+    assert_eq!(warnings.len(), 6);
+}
+
+#[test]
+fn validate_clean_env() {
+    let src = "root\n
+    @root.name \"Io\"\n
+    if !global.drunk \"hi `root.name\"\n
+    or \"hic\" next:await store\n
+;\n
+store\n
+    emit global.drunk\n
+    next:back\n
+;\n
+def global\n
+    drunk false\n
+;";
+
+    let env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    assert_eq!(env.validate(), vec![]);
+}
@@ -0,0 +1,108 @@
+extern crate lichen;
+
+use lichen::parse::Parser;
+use lichen::eval::Evaluator;
+use lichen::var::Var;
+use lichen::locale::{self,Locale,StringTable,LineEntry};
+
+use std::collections::HashMap;
+
+const SRC: &str = "root\n
+    @player.name \"Io\"\n
+    emit \"Halt!\" line:guard_halt\n
+    if !player.cursed \"Welcome, `player.name\"\n
+    emit:cycle \"Hi\" \"Hello\" line:guard_hello\n
+    emit \"Halt!\" line:guard_halt\n
+;";
+
+fn lines (env: &mut lichen::env::Env) -> Vec<Var> {
+    let ev = Evaluator::new(env);
+    ev.flat_map(|(vars, _)| vars).collect()
+}
+
+#[test]
+fn locale_ids() {
+    let env = Parser::parse_blocks(SRC).expect("ERROR: Unable to parse source").into_env();
+    let table = StringTable::new(&env);
+
+    let entry = |id: &str, text: &str| LineEntry { id: id.to_owned(), block: "root".to_owned(), text: text.to_owned() };
+    assert_eq!(table.entries, vec![entry("guard_halt", "Halt!"),
+                                   entry(&locale::line_id("root", "Welcome, `player.name"), "Welcome, `player.name"),
+                                   entry(&locale::line_id("root", "Hi"), "Hi"),
+                                   entry("guard_hello", "Hello")]);
+
+    // explicit IDs are not emitted
+    assert!(env.validate().is_empty());
+}
+
+#[test]
+fn locale_translate() {
+    let mut env = Parser::parse_blocks(SRC).expect("ERROR: Unable to parse source").into_env();
+    assert_eq!(lines(&mut env), vec!["Halt!".into(), "Welcome, Io".into(), "Hi".into(), "Halt!".into()]);
+
+    let mut fr = HashMap::new();
+    fr.insert("guard_halt".to_owned(), "Halte !".to_owned());
+    fr.insert(locale::line_id("root", "Welcome, `player.name"), "Bienvenue, `player.name".to_owned());
+    fr.insert("guard_hello".to_owned(), "Bonjour".to_owned());
+    env.set_locale(fr);
+
+    // formatting happens after translation, cycle continues on to its second line
+    assert_eq!(lines(&mut env), vec!["Halte !".into(), "Bienvenue, Io".into(), "Bonjour".into(), "Halte !".into()]);
+
+    env.clear_locale();
+    assert_eq!(lines(&mut env), vec!["Halt!".into(), "Welcome, Io".into(), "Hi".into(), "Halt!".into()]);
+}
+
+struct Shout;
+impl Locale for Shout {
+    fn translate (&self, _id: &str, text: &str) -> Option<String> {
+        Some(text.to_uppercase())
+    }
+}
+
+#[test]
+fn locale_custom() {
+    let mut env = Parser::parse_blocks(SRC).expect("ERROR: Unable to parse source").into_env();
+    env.set_locale(Shout);
+    assert_eq!(lines(&mut env)[0], "HALT!".into());
+}
+
+#[test]
+fn locale_ids_mid_line() {
+    let src = "root\n
+    if seen \"a\" line:x \"b\"\n
+    or \"c\" line:y \"d\"\n
+    if !seen \"e\" line:z \"f\"\n
+;";
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let ids: Vec<String> = StringTable::new(&env).entries.into_iter()
+        .map(|e| e.id).filter(|id| !id.starts_with("root:")).collect();
+    assert_eq!(ids, vec!["x".to_owned(), "y".to_owned(), "z".to_owned()]);
+
+    assert_eq!(lines(&mut env), vec!["c".into(), "d".into(), "e".into(), "f".into()]);
+}
+
+#[test]
+fn locale_csv_roundtrip() {
+    let src = "root\n
+    emit \"Well, hello there\" line:quoted\n
+    emit \"plain\" line:plain\n
+;";
+    let env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let csv = StringTable::new(&env).to_csv();
+    assert_eq!(csv, "id,block,text\nquoted,root,\"Well, hello there\"\nplain,root,plain\n");
+
+    let map = locale::parse_csv(&csv).expect("ERROR: Unable to parse table");
+    assert_eq!(map.get("quoted"), Some(&"Well, hello there".to_owned()));
+    assert_eq!(map.get("plain"), Some(&"plain".to_owned()));
+
+    // quotes are doubled within quoted fields, which may span lines
+    let map = locale::parse_csv("id,text\nq,\"Say \"\"hi\"\",\nthen go\"\r\n").expect("ERROR: Unable to parse table");
+    assert_eq!(map.get("q"), Some(&"Say \"hi\",\nthen go".to_owned()));
+
+    assert_eq!(locale::parse_csv("id,text\nbroken\n"), Err(2));
+    assert_eq!(locale::parse_csv("a,\"unterminated\n"), Err(2));
+
+    let po = StringTable::new(&env).to_po();
+    assert!(po.starts_with("#: root\nmsgctxt \"quoted\"\nmsgid \"Well, hello there\"\nmsgstr \"\"\n"));
+}
@@ -0,0 +1,78 @@
+extern crate lichen;
+
+use lichen::parse::Parser;
+use lichen::eval::Evaluator;
+use lichen::step::{Step,Meta};
+use lichen::var::Var;
+
+const SRC: &str = "root\n
+    emit @guard [mood:angry vo:g_012] \"Halt!\"\n
+    if (1 > 0) @guard [whisper] \"Who goes there?\" line:guard_who\n
+    or @narrator \"Silence.\"\n
+    emit \"Nobody speaks.\"\n
+    emit [\n
+        \"multiline\"\n
+    ]\n
+;";
+
+fn meta (speaker: Option<&str>, tags: &[(&str, &str)]) -> Meta {
+    Meta { speaker: speaker.map(|s| s.to_owned()),
+           tags: tags.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect() }
+}
+
+#[test]
+fn meta_steps() {
+    let mut env = Parser::parse_blocks(SRC).expect("ERROR: Unable to parse source").into_env();
+    assert!(env.validate().is_empty());
+
+    let mut ev = Evaluator::new(&mut env);
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["Halt!".into()],
+                                          meta: meta(Some("guard"), &[("mood", "angry"), ("vo", "g_012")]) }));
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["Who goes there?".into()],
+                                          meta: meta(Some("guard"), &[("whisper", "")]) }));
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["Nobody speaks.".into()], meta: Meta::default() }));
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["multiline".into()], meta: Meta::default() }));
+    assert_eq!(ev.step(), Ok(Step::Finished));
+}
+
+#[test]
+fn meta_iterator() {
+    let src = "root\n
+    if missing \"never\"\n
+    or @narrator [tone:dry] \"Silence.\"\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+    let (vars, _) = ev.next().unwrap();
+    assert_eq!(vars, vec![Var::from("Silence.")]);
+    assert_eq!(ev.meta(), &meta(Some("narrator"), &[("tone", "dry")]));
+}
+
+#[test]
+fn meta_speaker_only() {
+    let src = "root\n
+    emit:cycle @guard \"Halt!\" \"Stop!\"\n
+    emit @guard.captain \"At ease.\"\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["Halt!".into()], meta: meta(Some("guard"), &[]) }));
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["At ease.".into()], meta: meta(Some("guard.captain"), &[]) }));
+}
+
+#[test]
+fn meta_quoted_tags() {
+    let src = "root\n
+    emit @guard [vo:\"g 012\" mood:angry] \"Halt!\"\n
+    if (1 > 0) [note:\"a ] b\"] \"Who goes there?\"\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["Halt!".into()],
+                                          meta: meta(Some("guard"), &[("vo", "g 012"), ("mood", "angry")]) }));
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["Who goes there?".into()],
+                                          meta: meta(None, &[("note", "a ] b")]) }));
+}
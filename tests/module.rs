@@ -0,0 +1,122 @@
+extern crate lichen;
+
+use lichen::parse::{Parser,ParseErrorKind};
+use lichen::eval::Evaluator;
+use lichen::var::Var;
+
+const SMITH: &str = "module blacksmith\n
+def stock\n
+    swords 2\n
+;\n
+intro\n
+    emit \"The forge is hot.\"\n
+    @stock.swords - 1\n
+    next:now haggle\n
+;\n
+haggle\n
+    emit stock.swords \"left\"\n
+    next:now farewell\n
+;\n
+farewell\n
+    emit intro.visits\n
+;";
+
+#[test]
+fn module_relative() {
+    let src = format!("root\n
+    emit \"In town.\"\n
+    next:call blacksmith::intro\n
+;\n{}", SMITH);
+
+    let mut env = Parser::parse_blocks(&src).expect("ERROR: Unable to parse source").into_env();
+    assert!(env.src.contains_key("blacksmith::intro"));
+    assert!(env.def.contains_key("blacksmith::stock"));
+    assert!(env.validate().is_empty());
+
+    {
+        let ev = Evaluator::new(&mut env);
+        let vars: Vec<Var> = ev.flat_map(|(vars, _)| vars).collect();
+        assert_eq!(vars, vec!["In town.".into(),
+                              "The forge is hot.".into(),
+                              1.0.into(), "left".into(),
+                              Var::Int(1)]);
+    }
+
+    assert_eq!(env.def["blacksmith::stock"].data["swords"], 1.0.into());
+}
+
+#[test]
+fn module_global() {
+    // plain names resolve globally when the module has no such node
+    let src = format!("root\n
+    next:now blacksmith::intro\n
+;\n
+smithy\n
+    emit \"global smithy\"\n
+;\n{}", SMITH.replacen("next:now haggle", "next:now smithy", 1));
+
+    let mut env = Parser::parse_blocks(&src).expect("ERROR: Unable to parse source").into_env();
+    assert!(env.validate().iter().all(|w| w.to_string().contains("unreachable")));
+
+    let ev = Evaluator::new(&mut env);
+    let vars: Vec<Var> = ev.flat_map(|(vars, _)| vars).collect();
+    assert_eq!(vars, vec!["The forge is hot.".into(), "global smithy".into()]);
+}
+
+#[test]
+fn module_duplicate() {
+    let src = format!("{}\nmodule blacksmith\n
+intro\n
+    emit \"again\"\n
+;", SMITH);
+
+    let err = Parser::parse_blocks(&src).err().expect("ERROR: Invalid source parsed");
+    assert_eq!(err.kind, ParseErrorKind::DuplicateBlock);
+    assert_eq!(err.block, Some("blacksmith::intro".to_owned()));
+
+    // the same name within different modules is fine
+    let src = format!("{}\nmodule tailor\n
+intro\n
+    emit \"again\"\n
+;", SMITH);
+    let env = Parser::parse_blocks(&src).expect("ERROR: Unable to parse source").into_env();
+    assert!(env.src.contains_key("blacksmith::intro"));
+    assert!(env.src.contains_key("tailor::intro"));
+}
+
+#[test]
+fn module_try_insert() {
+    let mut env = Parser::parse_blocks(SMITH).expect("ERROR: Unable to parse source").into_env();
+    let more = || Parser::parse_blocks("module blacksmith\n
+haggle\n
+    emit \"no deals\"\n
+;").expect("ERROR: Unable to parse source").into_blocks();
+
+    let err = env.try_insert(more()).expect_err("ERROR: Duplicate inserted");
+    assert_eq!(err.kind, ParseErrorKind::DuplicateBlock);
+    assert_eq!(err.block, Some("blacksmith::haggle".to_owned()));
+    assert_eq!(env.src["blacksmith::haggle"].src.len(), 2);
+
+    env.insert(more());
+    assert_eq!(env.src["blacksmith::haggle"].src.len(), 1);
+}
+
+#[test]
+fn module_try_insert_nested_def() {
+    let src = || Parser::parse_blocks("def items.bag\n
+    rope 1i\n
+;").expect("ERROR: Unable to parse source").into_blocks();
+
+    let mut env = Parser::parse_blocks("root\n    emit \"hi\"\n;").expect("ERROR: Unable to parse source").into_env();
+    env.try_insert(src()).expect("ERROR: Unable to insert");
+
+    let err = env.try_insert(src()).expect_err("ERROR: Duplicate inserted");
+    assert_eq!(err.kind, ParseErrorKind::DuplicateBlock);
+    assert_eq!(err.block, Some("items.bag".to_owned()));
+}
+
+#[test]
+fn module_invalid() {
+    let err = Parser::parse_blocks("module black.smith\n").err().expect("ERROR: Invalid source parsed");
+    assert_eq!(err.kind, ParseErrorKind::InvalidModule);
+}
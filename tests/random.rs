@@ -0,0 +1,113 @@
+extern crate lichen;
+
+use lichen::parse::{Parser,ParseErrorKind};
+use lichen::eval::Evaluator;
+use lichen::source::Next;
+use lichen::var::Var;
+use lichen::rng::{Rng,XorShift};
+
+fn picks (seed: u64, n: usize) -> Vec<String> {
+    let src = "root\n
+    next:random {node_a 3, node_b 1, node_c 0}\n
+;\n
+node_a\n
+    emit \"a\"\n
+    next:now root\n
+;\n
+node_b\n
+    emit \"b\"\n
+    next:now root\n
+;\n
+node_c\n
+    emit \"c\"\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+    ev.seed(seed);
+
+    let mut picks = vec![];
+    while picks.len() < n {
+        let (vars, next) = ev.next().unwrap();
+        if let Some(Next::Now(ref nn)) = next {
+            if nn != "root" { picks.push(nn.clone()); }
+        }
+        else { assert!(!vars.is_empty()); }
+    }
+
+    picks
+}
+
+#[test]
+fn random_next() {
+    let a = picks(7, 400);
+    assert_eq!(a, picks(7, 400)); // deterministic for a seed
+
+    let count_a = a.iter().filter(|n| *n == "node_a").count();
+    assert!(a.iter().all(|n| n != "node_c"));
+    assert!((250..350).contains(&count_a));
+}
+
+#[test]
+fn random_chance() {
+    let src = "root\n
+    lucky chance 0.25\n
+    if lucky \"lucky\"\n
+    or \"unlucky\"\n
+    next:restart\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+    ev.seed(42);
+
+    let mut lucky = 0;
+    for _ in 0..400 {
+        let (vars,_) = ev.next().unwrap();
+        if vars[0] == "lucky".into() { lucky += 1; }
+        let _ = ev.next(); // restart
+    }
+
+    assert!((60..140).contains(&lucky));
+}
+
+#[test]
+fn random_custom_rng() {
+    // always rolls low
+    struct Low;
+    impl Rng for Low {
+        fn next_f32 (&mut self) -> f32 { 0. }
+    }
+
+    let src = "root\n
+    lucky chance root.odds\n
+    if lucky \"lucky\"\n
+    next:random {b 1, a 1}\n
+;\n
+def root\n
+    odds 0.01\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+    ev.set_rng(Low);
+
+    let (vars,_) = ev.next().unwrap();
+    assert_eq!(vars[0], Var::String("lucky".to_owned()));
+
+    let (_,next) = ev.next().unwrap();
+    assert_eq!(next, Some(Next::Now("a".to_owned())));
+
+    let mut rng = XorShift::new(0);
+    assert!((0..100).map(|_| rng.next_f32()).all(|n| (0. ..1.).contains(&n)));
+}
+
+#[test]
+fn random_invalid_weight() {
+    let src = "root\n
+    next:random {a \"heavy\"}\n
+;";
+
+    let err = Parser::parse_blocks(src).err().expect("ERROR: Expected parse error");
+    assert_eq!(err.kind, ParseErrorKind::InvalidNext);
+}
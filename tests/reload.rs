@@ -0,0 +1,146 @@
+extern crate lichen;
+
+use lichen::parse::Parser;
+use lichen::eval::{Evaluator,EvalError};
+use lichen::env::Reload;
+use lichen::var::Var;
+
+const SRC: &str = "def player\n
+    gold 10\n
+    name \"Ann\"\n
+;\n
+root\n
+    emit \"one\"\n
+    @player.gold + 5\n
+    emit \"two\"\n
+    emit \"three\"\n
+;\n
+shop\n
+    emit \"shop\"\n
+;";
+
+/// Next emitted vars, skipping over flow such as calls
+fn next (ev: &mut Evaluator) -> Vec<Var> {
+    ev.find(|&(ref vars, _)| !vars.is_empty()).map(|(vars, _)| vars).unwrap_or_default()
+}
+
+#[test]
+fn reload_keeps_state() {
+    let mut env = Parser::parse_blocks(SRC).expect("ERROR: Unable to parse source").into_env();
+    {
+        let mut ev = Evaluator::new(&mut env);
+        assert_eq!(next(&mut ev), vec!["one".into()]);
+        assert_eq!(next(&mut ev), vec!["two".into()]);
+
+        let src = SRC.replace("emit \"shop\"", "emit \"new shop\"")
+            .replace("name \"Ann\"", "name 1\n    hp 3")
+            + "\ninn\n    emit \"inn\"\n;";
+        let reload = ev.reload(Parser::parse_blocks(&src).expect("ERROR: Unable to parse source"));
+        assert_eq!(reload, Reload { changed: vec!["shop".to_owned()],
+                                    vanished: vec![],
+                                    added: vec!["inn".to_owned()] });
+
+        // root is unchanged, and carries on where it left off
+        assert_eq!(next(&mut ev), vec!["three".into()]);
+    }
+
+    let player = &env.def["player"].data;
+    assert_eq!(player["gold"], 15.0.into());
+    assert_eq!(player["name"], 1.0.into()); // type changed, source wins
+    assert_eq!(player["hp"], 3.0.into());
+    assert_eq!(env.visits("root"), 1);
+    assert_eq!(env.src["shop"].src.len(), 1);
+}
+
+#[test]
+fn reload_changed_restarts() {
+    let src = "root\n
+    emit \"a\"\n
+    emit \"b\"\n
+;";
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+    assert_eq!(next(&mut ev), vec!["a".into()]);
+
+    let reload = ev.reload(Parser::parse_blocks(&src.replace("\"b\"", "\"B\"")).expect("ERROR: Unable to parse source"));
+    assert_eq!(reload.changed, vec!["root".to_owned()]);
+    assert_eq!(next(&mut ev), vec!["a".into()]);
+    assert_eq!(next(&mut ev), vec!["B".into()]);
+}
+
+#[test]
+fn reload_vanished() {
+    let src = "root\n
+    emit \"r1\"\n
+    next:call side\n
+    emit \"r2\"\n
+;\n
+side\n
+    emit \"s1\"\n
+    emit \"s2\"\n
+;";
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    {
+        let mut ev = Evaluator::new(&mut env);
+        assert_eq!(next(&mut ev), vec!["r1".into()]);
+        assert_eq!(next(&mut ev), vec!["s1".into()]);
+
+        let src = "root\n
+    emit \"r1\"\n
+    next:call side\n
+    emit \"r2\"\n
+;";
+        let reload = ev.reload(Parser::parse_blocks(src).expect("ERROR: Unable to parse source"));
+        assert_eq!(reload.vanished, vec!["side".to_owned()]);
+        assert!(reload.changed.is_empty() && reload.added.is_empty());
+
+        // the caller survives, and resumes after the call
+        assert_eq!(next(&mut ev), vec!["r2".into()]);
+    }
+
+    assert!(!env.src.contains_key("side"));
+    assert!(!env.visits.contains_key("side"));
+}
+
+#[test]
+fn reload_pending() {
+    let src = "root\n
+    next:select {a shop_a, b shop_b}\n
+;\n
+shop_a\n
+    emit \"a\"\n
+;\n
+shop_b\n
+    emit \"b\"\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    {
+        let mut ev = Evaluator::new(&mut env);
+        ev.step().expect("ERROR: Unable to step"); // choice
+
+        let src = src.replace("shop_b\n\n    emit \"b\"\n\n;", "");
+        let reload = ev.reload(Parser::parse_blocks(&src).expect("ERROR: Unable to parse source"));
+        assert_eq!(reload.vanished, vec!["shop_b".to_owned()]);
+
+        assert_eq!(ev.select("b"), Err(EvalError::InvalidChoice("b".to_owned())));
+        assert_eq!(ev.select("a"), Ok(()));
+        assert_eq!(next(&mut ev), vec!["a".into()]);
+    }
+
+    let src = "root\n
+    if (1 > 0) \"r1\" next:await side\n
+;\n
+side\n
+    emit \"s1\"\n
+;";
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+    ev.step().expect("ERROR: Unable to step"); // line, with the await queued
+    assert!(ev.pending().is_some());
+
+    ev.reload(Parser::parse_blocks("root\n    if (1 > 0) \"r1\" next:await side\n;").expect("ERROR: Unable to parse source"));
+    assert_eq!(ev.pending(), None);
+    assert_eq!(ev.select("side"), Err(EvalError::NotPending("side".to_owned())));
+    assert_eq!(ev.step(), Ok(lichen::step::Step::Finished));
+}
@@ -5,6 +5,7 @@ use lichen::parse::Parser;
 use lichen::eval::Evaluator;
 
 #[test]
+#[allow(clippy::len_zero)]
 fn bitrot() {
     let mut src = vec![("basic", String::from_utf8_lossy(include_bytes!("../examples/basic.ls"))),
                        ("contrived", String::from_utf8_lossy(include_bytes!("../examples/contrived.ls"))),
@@ -0,0 +1,72 @@
+extern crate lichen;
+
+use lichen::parse::{Parser,ParseErrorKind};
+use lichen::eval::Evaluator;
+use lichen::var::Var;
+
+const SRC: &str = "root\n
+    emit:once \"Hello, stranger\" \"Back so soon?\"\n
+    emit:cycle \"Hi\" \"Hello\"\n
+    emit:stopping \"Fresh bread!\" \"Still warm\" \"Stale, sorry\"\n
+    next:restart\n
+;";
+
+fn take (ev: &mut Evaluator, n: usize) -> Vec<Var> {
+    ev.take(n).flat_map(|(vars, _)| vars).collect()
+}
+
+#[test]
+fn sequences_survive_restart() {
+    let mut env = Parser::parse_blocks(SRC).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+
+    // each pass emits its lines then restarts
+    assert_eq!(take(&mut ev, 4), vec!["Hello, stranger".into(), "Hi".into(), "Fresh bread!".into()]);
+    assert_eq!(take(&mut ev, 4), vec!["Back so soon?".into(), "Hello".into(), "Still warm".into()]);
+    assert_eq!(take(&mut ev, 3), vec!["Hi".into(), "Stale, sorry".into()]);
+    assert_eq!(take(&mut ev, 3), vec!["Hello".into(), "Stale, sorry".into()]);
+}
+
+#[test]
+fn sequences_survive_advance() {
+    let mut env = Parser::parse_blocks(SRC).expect("ERROR: Unable to parse source").into_env();
+    {
+        let mut ev = Evaluator::new(&mut env);
+        let _ = ev.next(); // once
+        ev.advance("root".to_owned());
+        assert_eq!(ev.next().unwrap().0, vec!["Back so soon?".into()]);
+    }
+
+    assert_eq!(env.src["root"].seqs.get(&0), Some(&2));
+}
+
+#[test]
+fn sequence_shuffle() {
+    let src = "root\n
+    emit:shuffle \"a\" \"b\" \"c\"\n
+    next:restart\n
+;";
+
+    let picks = |seed| {
+        let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+        let mut ev = Evaluator::new(&mut env);
+        ev.seed(seed);
+        take(&mut ev, 20)
+    };
+
+    let a = picks(7);
+    assert_eq!(a.len(), 10);
+    assert!(a.iter().all(|v| ["a", "b", "c"].iter().any(|s| v == &Var::from(*s))));
+    assert_eq!(a, picks(7)); // seeded picks repeat
+}
+
+#[test]
+fn sequence_unknown_tag() {
+    let src = "root\n
+    emit:sometimes \"a\" \"b\"\n
+;";
+
+    let err = Parser::parse_blocks(src).err().expect("ERROR: Invalid source parsed");
+    assert_eq!(err.kind, ParseErrorKind::UnknownTag);
+    assert_eq!(err.token, Some("emit:sometimes".to_owned()));
+}
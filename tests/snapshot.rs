@@ -0,0 +1,142 @@
+extern crate lichen;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+
+use lichen::parse::Parser;
+use lichen::eval::Evaluator;
+use lichen::snapshot::Snapshot;
+use lichen::var::Var;
+
+const SRC: &str = "root\n
+    has_coins global.coins > 0\n
+    @global.coins + 5\n
+    emit \"step\"\n
+    if has_coins \"coins `global.coins\"\n
+    next:call shop\n
+    emit \"done\"\n
+;\n
+shop\n
+    emit \"shopping\"\n
+    emit \"still shopping\"\n
+;\n
+def global\n
+    coins 0\n
+;";
+
+fn resume (snapshot: Snapshot) {
+    let mut env = Parser::parse_blocks(SRC).expect("ERROR: Unable to parse source").into_env();
+    let state = snapshot.restore(&mut env).expect("ERROR: Unable to restore");
+
+    assert_eq!(env.visits("root"), 1);
+    assert_eq!(env.src["root"].emitted.len(), 1);
+
+    let mut ev = state.to_eval(&mut env);
+    let (vars,_) = ev.next().unwrap();
+    assert_eq!(vars[0], "coins 5".into());
+    ev.next().unwrap(); // call
+    let (vars,_) = ev.next().unwrap();
+    assert_eq!(vars[0], "shopping".into());
+}
+
+fn take_snapshot () -> Snapshot {
+    let mut env = Parser::parse_blocks(SRC).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+    let (vars,_) = ev.next().unwrap();
+    assert_eq!(vars[0], "step".into());
+
+    ev.snapshot()
+}
+
+#[test]
+fn snapshot_restore() {
+    resume(take_snapshot());
+}
+
+#[test]
+fn snapshot_version() {
+    let mut snapshot = take_snapshot();
+    snapshot.version += 1;
+    
+    let mut env = Parser::parse_blocks(SRC).expect("ERROR: Unable to parse source").into_env();
+    assert!(snapshot.restore(&mut env).is_err());
+
+    // older versions are still restored
+    let mut snapshot = take_snapshot();
+    snapshot.version = 1;
+    resume(snapshot);
+}
+
+#[test]
+fn snapshot_chance() {
+    let src = "root\n
+    lucky chance 1\n
+    emit \"rolled\"\n
+    if lucky \"lucky\"\n
+    or \"unlucky\"\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let snapshot = {
+        let mut ev = Evaluator::new(&mut env);
+        let (vars,_) = ev.next().unwrap();
+        assert_eq!(vars[0], "rolled".into());
+        ev.snapshot()
+    };
+    assert_eq!(snapshot.nodes["root"].chances.get("lucky"), Some(&true));
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let state = snapshot.restore(&mut env).expect("ERROR: Unable to restore");
+    let mut ev = state.to_eval(&mut env);
+    let (vars,_) = ev.next().unwrap();
+    assert_eq!(vars[0], "lucky".into());
+}
+
+#[test]
+fn snapshot_rng() {
+    let src = "root\n
+    next:random {a 1, b 1, c 1}\n
+;\n
+a\n
+    emit \"a\"\n
+    next:now root\n
+;\n
+b\n
+    emit \"b\"\n
+    next:now root\n
+;\n
+c\n
+    emit \"c\"\n
+    next:now root\n
+;";
+
+    let rolls = |ev: &mut Evaluator| -> Vec<Var> {
+        ev.take(40).flat_map(|(vars, _)| vars).collect()
+    };
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+    ev.seed(11);
+    rolls(&mut ev);
+
+    let snapshot = ev.snapshot();
+    let expected = rolls(&mut ev);
+
+    #[cfg(feature = "serde")]
+    let snapshot: Snapshot = {
+        let json = serde_json::to_string(&snapshot).expect("ERROR: Unable to serialize");
+        serde_json::from_str(&json).expect("ERROR: Unable to deserialize")
+    };
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let state = snapshot.restore(&mut env).expect("ERROR: Unable to restore");
+    let mut ev = state.to_eval(&mut env);
+    assert_eq!(rolls(&mut ev), expected);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn snapshot_serde() {
+    let json = serde_json::to_string(&take_snapshot()).expect("ERROR: Unable to serialize");
+    let snapshot: Snapshot = serde_json::from_str(&json).expect("ERROR: Unable to deserialize");
+    resume(snapshot);
+}
@@ -2,10 +2,12 @@ extern crate lichen;
 
 use lichen::parse::Parser;
 use lichen::var::Var;
-use lichen::eval::Evaluator;
+use lichen::eval::{Eval,Evaluator};
 use lichen::fun::Fun;
+use lichen::lint::Warning;
 
 use std::sync::{Arc,Mutex};
+use std::collections::BTreeMap;
 
 // Test for mutable state
 #[derive(Debug)]
@@ -14,6 +16,33 @@ struct Player {
     name: String
 }
 
+impl Eval for Player {
+    fn get (&self, path: Option<Vec<&str>>, lookup: &str) -> Option<Var> {
+        if path != Some(vec!["player"]) { return None }
+        match lookup {
+            "coins" => Some(Var::Num(self.coins)),
+            "name" => Some(Var::String(self.name.clone())),
+            _ => None,
+        }
+    }
+
+    fn set (&mut self, path: Option<Vec<&str>>, lookup: &str, var: Var) {
+        if path != Some(vec!["player"]) { return }
+        match (lookup, var) {
+            ("coins", Var::Num(n)) => { self.coins = n; },
+            ("name", Var::String(s)) => { self.name = s; },
+            _ => {},
+        }
+    }
+
+    fn call (&mut self, _var: Var, fun: &str, vars: &Vec<Var>) -> Option<Var> {
+        match (fun, vars.first()) {
+            ("mul", Some(&Var::Num(n))) => Some(Var::Num(self.coins * n)),
+            _ => None,
+        }
+    }
+}
+
 #[test]
 fn state_mut() {
     let src = "\n
@@ -35,6 +64,7 @@ root\n
 }
 
 #[test]
+#[allow(clippy::needless_return)]
 fn parse_cust_fn() {
     let src = "root\n
     @root.five (inc) 1 2 3\n
@@ -205,3 +235,103 @@ def global\n
     let (vars,_) = ev.next().unwrap();
     assert_eq!(vars[0], "is drunk".into());
 }
+
+#[test]
+fn parse_collection_def() {
+    let src = "def player\n
+    coins 100i\n
+    inventory [\"sword\" \"shield\"\n
+               2.5 true]\n
+    quests {main \"started\", side \"none\" 1i}\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let player = env.def.remove("player").expect("ERROR: No def block");
+
+    assert_eq!(player.data["coins"], Var::Int(100));
+    assert_eq!(player.data["inventory"], Var::List(vec!["sword".into(), "shield".into(),
+                                                        2.5 .into(), true.into()]));
+
+    let mut quests = BTreeMap::new();
+    quests.insert("main".to_owned(), "started".into());
+    quests.insert("side".to_owned(), Var::List(vec!["none".into(), Var::Int(1)]));
+    assert_eq!(player.data["quests"], Var::Map(quests));
+}
+
+#[test]
+fn validate_collection_mut() {
+    let src = "root\n
+    @player.coins + 5i\n
+    @player.inventory push \"potion\"\n
+    @player.inventory remove \"sword\"\n
+    @player.quests push \"side\" \"started\"\n
+    @player.has_sword contains player.inventory \"sword\"\n
+    has_potion \"potion\" in player.inventory\n
+    on_quest (\"side\" in player.quests and player.coins == 105)\n
+    comp:all [has_potion on_quest !player.has_sword]\n
+    if comp player.coins player.inventory\n
+;\n
+def player\n
+    coins 100i\n
+    inventory [\"sword\" \"shield\"]\n
+    quests {main \"started\"}\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    
+    let mut ev = Evaluator::new(&mut env);
+    let (vars,_) = ev.next().unwrap();
+    
+    assert_eq!(vars[0], Var::Int(105));
+    assert_eq!(vars[1], Var::List(vec!["shield".into(), "potion".into()]));
+}
+
+#[test]
+fn nested_def_paths() {
+    let src = "root\n
+    @player.bag.pouch.coins 1i\n
+    @player.bag.pouch.coins + 2i\n
+    @stash new items.bag\n
+    has_bag player.bag\n
+    if has_bag player.bag.pouch.coins stash.rope\n
+;\n
+def items.bag\n
+    rope \"hemp\"\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    assert!(env.def["items"].blocks.contains_key("bag"));
+    assert!(!env.validate().iter().any(|w| matches!(w, &Warning::UndefinedDef{..})));
+    
+    let mut ev = Evaluator::new(&mut env);
+    let (vars,_) = ev.next().unwrap();
+    
+    assert_eq!(vars[0], Var::Int(3));
+    assert_eq!(vars[1], "hemp".into());
+}
+
+#[test]
+fn custom_store() {
+    let src = "root\n
+    @player.coins + 5\n
+    @player.coins (mul) 2\n
+    rich player.coins > 20\n
+    if rich \"`player.name is rich\"\n
+    emit player.coins\n
+;";
+
+    let player = Player { coins: 10., name: "Bob".to_owned() };
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source")
+        .into_env().with_store(player);
+
+    {
+        let mut ev = Evaluator::new(&mut env);
+        let (vars,_) = ev.next().unwrap();
+        assert_eq!(vars[0], "Bob is rich".into());
+
+        let (vars,_) = ev.next().unwrap();
+        assert_eq!(vars[0], 30.0 .into());
+    }
+
+    assert_eq!(env.def.coins, 30.);
+}
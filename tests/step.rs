@@ -0,0 +1,172 @@
+extern crate lichen;
+
+use lichen::parse::Parser;
+use lichen::eval::{Evaluator,EvalError};
+use lichen::step::{Step,Choice,Meta};
+use lichen::source::Next;
+use lichen::var::Var;
+
+const SRC: &str = "root\n
+    emit \"hello\"\n
+    next:now town\n
+;\n
+town\n
+    if !global.visited \"welcome to town\" next:await store\n
+    @global.visited true\n
+    next:select {\"Shop?\" store,\n
+                \"Leave?\" exit}\n
+    emit \"nobody chose\"\n
+;\n
+store\n
+    emit \"in store\"\n
+;\n
+exit\n
+    next:exit\n
+    emit \"unreachable\"\n
+;";
+
+#[test]
+fn step_lines_and_pauses() {
+    let mut env = Parser::parse_blocks(SRC).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["hello".into()], meta: Meta::default() }));
+
+    // flow between nodes is silent, line comes before its await
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["welcome to town".into()], meta: Meta::default() }));
+    assert_eq!(ev.step(), Ok(Step::Await { node: "store".to_owned() }));
+
+    // skipping the await continues on
+    let options = vec![Choice { key: "Leave?".to_owned(), node: "exit".to_owned(), enabled: true },
+                       Choice { key: "Shop?".to_owned(), node: "store".to_owned(), enabled: true }];
+    assert_eq!(ev.step(), Ok(Step::Choice { options: options }));
+
+    assert_eq!(ev.choose("Stay?"), Err(EvalError::InvalidChoice("Stay?".to_owned())));
+    assert_eq!(ev.choose("Shop?"), Ok(()));
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["in store".into()], meta: Meta::default() }));
+    assert_eq!(ev.step(), Ok(Step::Finished));
+}
+
+#[test]
+fn step_choice_expires() {
+    let mut env = Parser::parse_blocks(SRC).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+
+    while let Ok(step) = ev.step() {
+        if let Step::Choice { .. } = step { break }
+    }
+
+    // stepping past a select skips it
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["nobody chose".into()], meta: Meta::default() }));
+    assert!(ev.choose("Leave?").is_err());
+    assert_eq!(ev.step(), Ok(Step::Finished));
+
+    // exit ends evaluation
+    ev.advance("root".to_owned());
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["hello".into()], meta: Meta::default() }));
+    match ev.step() {
+        Ok(Step::Choice { .. }) => {},
+        step => panic!("Expected choice, found {:?}", step),
+    }
+    assert!(ev.choose("Leave?").is_ok());
+    assert_eq!(ev.step(), Ok(Step::Finished));
+}
+
+#[test]
+fn select_checked() {
+    let mut env = Parser::parse_blocks(SRC).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+
+    assert_eq!(ev.select("store"), Err(EvalError::NotPending("store".to_owned())));
+
+    let _ = ev.next(); // hello
+    let _ = ev.next(); // town
+    let (_,next) = ev.next().unwrap();
+    assert_eq!(next, Some(Next::Await("store".to_owned())));
+    assert_eq!(ev.pending(), Some(&Next::Await("store".to_owned())));
+
+    // only the awaited node is accepted
+    assert_eq!(ev.select("exit"), Err(EvalError::InvalidChoice("exit".to_owned())));
+
+    // pending state survives saving
+    let state = ev.save();
+    let mut ev = state.to_eval(&mut env);
+    assert_eq!(ev.select("store"), Ok(()));
+    assert_eq!(ev.pending(), None);
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["in store".into()], meta: Meta::default() }));
+
+    // select keys map to their nodes, nodes themselves are not keys
+    ev.advance("town".to_owned());
+    let _ = ev.step(); // welcome
+    let _ = ev.step(); // await, skipped
+    match ev.step() {
+        Ok(Step::Choice { .. }) => {},
+        step => panic!("Expected choice, found {:?}", step),
+    }
+    assert_eq!(ev.select("exit"), Err(EvalError::InvalidChoice("exit".to_owned())));
+    assert_eq!(ev.select("Leave?"), Ok(()));
+    assert_eq!(ev.step(), Ok(Step::Finished));
+}
+
+#[test]
+fn select_guarded() {
+    let src = "root\n
+    rich player.gold > 10\n
+    next:select {\"[Bribe]\" bribe if rich,\n
+                \"[Threaten]\" threaten if (player.strength >= 5 and !player.cursed),\n
+                \"Leave\" exit}\n
+;\n
+bribe\n
+    emit \"bribed\"\n
+;\n
+threaten\n
+    emit \"threatened\"\n
+;\n
+exit\n
+    emit \"left\"\n
+;\n
+def player\n
+    gold 5\n
+    strength 8\n
+    cursed false\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    assert_eq!(env.validate(), vec![]);
+
+    {
+        let mut ev = Evaluator::new(&mut env);
+        let (_,next) = ev.next().unwrap();
+        match next {
+            Some(Next::Select(map)) => {
+                assert_eq!(map.len(), 2);
+                assert_eq!(map["[Threaten]"], vec![Var::Sym("threaten".to_owned())]);
+                assert!(!map.contains_key("[Bribe]"));
+            },
+            _ => panic!("Expected select"),
+        }
+
+        assert_eq!(ev.select("[Bribe]"), Err(EvalError::InvalidChoice("[Bribe]".to_owned())));
+    }
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+    ev.show_disabled(true);
+    let options = vec![Choice { key: "Leave".to_owned(), node: "exit".to_owned(), enabled: true },
+                       Choice { key: "[Bribe]".to_owned(), node: "bribe".to_owned(), enabled: false },
+                       Choice { key: "[Threaten]".to_owned(), node: "threaten".to_owned(), enabled: true }];
+    assert_eq!(ev.step(), Ok(Step::Choice { options: options }));
+    assert!(ev.choose("[Bribe]").is_err());
+    assert_eq!(ev.choose("[Threaten]"), Ok(()));
+    assert_eq!(ev.step(), Ok(Step::Line { vars: vec!["threatened".into()], meta: Meta::default() }));
+}
+
+#[test]
+fn select_guard_invalid() {
+    let src = "root\n
+    next:select {\"[Bribe]\" bribe if,\n
+                \"Leave\" exit}\n
+;";
+
+    assert!(Parser::parse_blocks(src).is_err());
+}
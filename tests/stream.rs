@@ -2,7 +2,7 @@ extern crate lichen;
 
 use std::io::Cursor;
 
-use lichen::parse::{StreamParser,Block};
+use lichen::parse::{StreamParser,StreamError,Block,ParseErrorKind};
 use lichen::source::Src;
 use lichen::var::Var;
 use lichen::env::Env;
@@ -11,6 +11,7 @@ use lichen::eval::Evaluator;
 
 
 #[test]
+#[allow(clippy::redundant_slicing, clippy::get_first)]
 fn stream_parser() {
     let src = "root\n
     emit \"hi\"\n
@@ -76,3 +77,112 @@ emi";  //unfinished source
     let (vars,_) = ev.nth(1).expect("ERROR: Block failed to transition");
     assert_eq!(vars.get(0), Some(&Var::String("hi again".to_owned())));
 }
+
+fn names (s: &StreamParser<Cursor<&[u8]>>) -> Vec<String> {
+    s.blocks.iter().map(|b| b.name().to_owned()).collect()
+}
+
+#[test]
+fn stream_utf8_split() {
+    let src = "root\n
+    emit \"Grüße, señor ✓\"\n
+;";
+
+    // every multibyte character is split across reads
+    let mut s = StreamParser::new(Cursor::new(src.as_bytes()), Some(1));
+    assert_eq!(s.by_ref().count(), 1);
+    assert!(s.error().is_none());
+
+    match s.blocks[0] {
+        Block::Src(ref b) => {
+            assert_eq!(b.src[0], Src::Emit(vec![Var::String("Grüße, señor ✓".to_owned())]));
+        },
+        _ => { panic!("ERROR: Invalid block type") }
+    }
+}
+
+#[test]
+fn stream_quoted_semicolon() {
+    let src = "root\n
+    emit \"wait; what\" # not done; really\n
+    emit \"done\"\n
+;\n
+# trailing comment;\n";
+
+    let mut s = StreamParser::new(Cursor::new(src.as_bytes()), Some(7));
+    assert_eq!(s.by_ref().collect::<Vec<_>>(), vec![0]);
+    assert!(s.error().is_none());
+
+    match s.blocks[0] {
+        Block::Src(ref b) => {
+            assert_eq!(b.src.len(), 2);
+            assert_eq!(b.src[0], Src::Emit(vec![Var::String("wait; what".to_owned())]));
+        },
+        _ => { panic!("ERROR: Invalid block type") }
+    }
+
+    // nothing is left buffered, only a comment
+    assert!(s.sink(&mut Env::empty()).is_ok());
+}
+
+#[test]
+fn stream_errors() {
+    let src = "root\n
+    emit \"hi\"\n
+;\n
+broken\n
+    next:bogus\n
+;\n
+after\n
+    emit \"ok\"\n
+;";
+
+    // blocks around the broken one are kept, within the same read
+    let mut s = StreamParser::new(Cursor::new(src.as_bytes()), None);
+    assert_eq!(s.try_parse().expect("ERROR: Stream failed"), Some(0));
+    assert_eq!(names(&s), vec!["root".to_owned(), "after".to_owned()]);
+
+    let errors = s.take_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind, ParseErrorKind::InvalidTag);
+    assert_eq!(errors[0].block, Some("broken".to_owned()));
+    assert_eq!((errors[0].line, errors[0].col), (9, 5)); // located within the stream
+    assert!(s.take_errors().is_empty());
+
+    // and across reads, iteration carries on past the broken block
+    let mut s = StreamParser::new(Cursor::new(src.as_bytes()), Some(8));
+    assert_eq!(s.by_ref().collect::<Vec<_>>(), vec![0, 1]);
+    assert!(s.error().is_none());
+    assert_eq!(names(&s), vec!["root".to_owned(), "after".to_owned()]);
+    assert_eq!(s.take_errors().len(), 1);
+
+    // invalid UTF-8 ends iteration, rather than being skipped over
+    let bytes: &[u8] = b"root\n    emit \"\xff\"\n;";
+    let mut s = StreamParser::new(Cursor::new(bytes), Some(4));
+    assert_eq!(s.by_ref().count(), 0);
+    match s.error() {
+        Some(&StreamError::Utf8(offset)) => assert_eq!(offset, 15),
+        e => { panic!("ERROR: Invalid error {:?}", e) }
+    }
+}
+
+#[test]
+fn stream_module() {
+    let src = "module smith\n
+intro\n
+    next:now haggle\n
+;\n
+haggle\n
+    emit \"deal\"\n
+;\n
+intro\n
+;";
+
+    let mut s = StreamParser::new(Cursor::new(src.as_bytes()), Some(16));
+    assert_eq!(s.by_ref().collect::<Vec<_>>(), vec![0, 1]);
+    assert_eq!(names(&s), vec!["smith::intro".to_owned(), "smith::haggle".to_owned()]);
+
+    let errors = s.take_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind, ParseErrorKind::DuplicateBlock);
+}
@@ -1,12 +1,14 @@
 extern crate lichen;
 
-use lichen::parse::{Parser,Block,SrcBlock,Map};
+use lichen::parse::{Parser,Block,SrcBlock,Map,ParseErrorKind};
 use lichen::logic::{Logic,Expect};
 use lichen::var::{Var,Mut};
 use lichen::source::{Src,Next};
-use lichen::eval::Evaluator;
+use lichen::eval::{Evaluator,EvalError};
+use lichen::expr::{Expr,Op,Cmp};
+use lichen::locale::line_id;
 
-use std::collections::HashMap;
+use std::collections::{HashMap,BTreeMap,BTreeSet};
 
 
 #[test]
@@ -55,12 +57,21 @@ fn parse_block() {
                               None),
                       Src::Next(Next::Now("end".to_owned()))],
             logic: HashMap::new(),
+            params: vec![],
+            locals: HashMap::new(),
+            emitted: BTreeSet::new(),
+            seqs: BTreeMap::new(),
+            chances: BTreeMap::new(),
+            line_ids: [((6,0), line_id("root", "you're looking for something?")),
+                       ((7,0), line_id("root", "welcome, \nlook around"))].iter().cloned().collect(),
+            meta: BTreeMap::new(),
         })];
     
     assert_eq!(block[0],block_[0]);
 }
 
 #[test]
+#[allow(clippy::needless_late_init)]
 fn parse_qsym_block() {
     let src = "root\n
     if !some_item \"you're looking for something?\"\n
@@ -86,6 +97,7 @@ fn parse_qsym_block() {
 }
 
 #[test]
+#[allow(clippy::needless_late_init)]
 fn parse_qsym_comp_block() {
     let src =  "root\n
     has_weight some_weight < 5.0\n
@@ -307,7 +319,7 @@ step2
     let mut ev = Evaluator::new(&mut env);
     
     let (_,next) = ev.next().unwrap();
-    assert_eq!(next, Some(Next::Call("step2".to_owned())));
+    assert_eq!(next, Some(Next::Call("step2".to_owned(), vec![])));
 
     let (_,next) = ev.next().unwrap();
     assert_eq!(next, Some(Next::Back));
@@ -428,3 +440,307 @@ fn inline_logic_inv() {
     let (vars,_) = ev.next().unwrap();
     assert_eq!(vars[0], 5. .into());
 }
+
+#[test]
+fn parse_error_location() {
+    let src = "root\n    emit \"hi\"\n    heavy weight >> 5\n;";
+
+    let err = Parser::parse_blocks(src).err().expect("ERROR: Invalid source parsed");
+    assert_eq!(err.kind, ParseErrorKind::InvalidLogic);
+    assert_eq!(err.block, Some("root".to_owned()));
+    assert_eq!(err.token, Some(">>".to_owned()));
+    assert_eq!((err.line, err.col), (3, 18));
+}
+
+#[test]
+fn parse_error_or_without_if() {
+    let src = "root\n    emit \"hi\"\n    or \"nope\"\n;";
+
+    let err = Parser::parse_blocks(src).err().expect("ERROR: Invalid source parsed");
+    assert_eq!(err.kind, ParseErrorKind::OrWithoutIf);
+    assert_eq!((err.line, err.col), (3, 5));
+}
+
+#[test]
+fn parse_error_unclosed_map() {
+    let src = "root\n    next:select {a other,\n    emit \"b\"\n;\nother\n    emit \"c\"\n;";
+
+    let err = Parser::parse_blocks(src).err().expect("ERROR: Invalid source parsed");
+    assert_eq!(err.kind, ParseErrorKind::UnbalancedMap);
+    assert_eq!(err.block, Some("root".to_owned()));
+    assert_eq!((err.line, err.col), (2, 17));
+}
+
+#[test]
+fn parse_error_unknown_function() {
+    let src = "root\n    @coins ( 1\n;";
+
+    let err = Parser::parse_blocks(src).err().expect("ERROR: Invalid source parsed");
+    assert_eq!(err.kind, ParseErrorKind::UnknownFunction);
+    assert_eq!(err.token, Some("(".to_owned()));
+}
+
+#[test]
+fn parse_error_unbuilt_block() {
+    let src = "root\n    emit \"hi\"\n;\n;";
+
+    let err = Parser::parse_blocks(src).err().expect("ERROR: Invalid source parsed");
+    assert_eq!(err.kind, ParseErrorKind::BlockNotBuilt);
+    assert_eq!(err.block, None);
+    assert_eq!((err.line, err.col), (4, 1));
+}
+
+#[test]
+fn parse_recover_blocks() {
+    let src = "root\n
+    emit \"hi\"\n
+    or \"broken\" # or without if\n
+    emit \"still broken;\"\n
+;\n
+store\n
+    emit \"welcome\"\n
+;\n
+town\n
+    heavy weight >> 5\n
+;\n
+def global\n
+    name \"my-game\"\n
+;";
+
+    let (p, errs) = Parser::parse_blocks_recover(src);
+    
+    assert_eq!(errs.len(), 2);
+    assert_eq!(errs[0].kind, ParseErrorKind::OrWithoutIf);
+    assert_eq!(errs[0].block, Some("root".to_owned()));
+    assert_eq!(errs[1].kind, ParseErrorKind::InvalidLogic);
+    assert_eq!(errs[1].block, Some("town".to_owned()));
+
+    assert_eq!(p.len(), 2);
+    assert_eq!(p[0].name(), "store");
+    assert_eq!(p[1].name(), "global");
+}
+
+#[test]
+fn parse_recover_unclosed_map() {
+    let src = "root\n
+    emit \"a\"\n
+    next:select {a other,\n
+    emit \"b\"\n
+;\n
+other\n
+    emit \"c\"\n
+;";
+
+    let (p, errs) = Parser::parse_blocks_recover(src);
+
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].kind, ParseErrorKind::UnbalancedMap);
+    assert_eq!(errs[0].block, Some("root".to_owned()));
+    assert_eq!((errs[0].line, errs[0].col), (5, 17));
+
+    assert_eq!(p.len(), 1);
+    assert_eq!(p[0].name(), "other");
+}
+
+#[test]
+fn parse_recover_malformed() {
+    let src = "a\n
+    @coins\n
+;\n
+b\n
+    lg \"\"\n
+;\n
+c\n
+    next:select\n
+;\n
+d\n
+    emit \"fine\"\n
+;";
+
+    let (p, errs) = Parser::parse_blocks_recover(src);
+    let kinds: Vec<ParseErrorKind> = errs.iter().map(|e| e.kind).collect();
+    assert_eq!(kinds, vec![ParseErrorKind::InvalidArgs,
+                           ParseErrorKind::InvalidLogic,
+                           ParseErrorKind::InvalidNext]);
+    assert_eq!(errs[0].token, Some("@coins".to_owned()));
+
+    assert_eq!(p.len(), 1);
+    assert_eq!(p[0].name(), "d");
+}
+
+#[test]
+fn parse_logic_expr() {
+    let src = "root\n
+    low_hp player.hp < (player.max_hp * 0.5)\n
+;";
+
+    let block = Parser::parse_blocks(src).expect("ERROR: Unable to parse source");
+    match &block[0] {
+        &Block::Src(ref b) => {
+            let e = Expr::Cmp(Cmp::LT,
+                              Box::new(Expr::Var(Var::Sym("player.hp".to_owned()))),
+                              Box::new(Expr::Math(Op::Mul,
+                                                  Box::new(Expr::Var(Var::Sym("player.max_hp".to_owned()))),
+                                                  Box::new(Expr::Var(Var::Num(0.5))))));
+            assert_eq!(b.src[0], Src::Logic("low_hp".to_owned(), Logic::Expr(e)));
+        },
+        _ => panic!("unknown block found")
+    }
+}
+
+#[test]
+fn validate_logic_expr() {
+    let src = "root\n
+    rich player.gold >= 10\n
+    leveled player.level == 3\n
+    stranger player.name != \"Bob\"\n
+    low_hp player.hp < player.max_hp * 0.5 + 1\n
+    broke player.gold <= 0\n
+    comp:all [rich leveled !stranger low_hp !broke]\n
+    if comp \"all good\"\n
+    or \"not quite\"\n
+;\n
+def player\n
+    gold 10\n
+    level 3\n
+    name \"Bob\"\n
+    hp 5\n
+    max_hp 10\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+
+    let mut ev = Evaluator::new(&mut env);
+    let (vars,_) = ev.next().unwrap();
+    assert_eq!(vars[0], "all good".into());
+}
+
+#[test]
+fn validate_inline_logic() {
+    let src = "root\n
+    if (player.gold > 10 and !player.cursed) \"welcome\" next:now shop\n
+    or \"go away\"\n
+;\n
+shop\n
+    if (player.name == \"Io\" or not (player.gold >= 100)) \"browse\"\n
+    when {(player.gold > 10) @player.gold - 10,\n
+          needs_coins @player.name \"Bob\"}\n
+    emit player.gold player.name\n
+;\n
+def player\n
+    gold 20\n
+    name \"Io\"\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+
+    {
+        let mut ev = Evaluator::new(&mut env);
+        let (vars,next) = ev.next().unwrap();
+        assert_eq!(vars[0], "welcome".into());
+        assert_eq!(next, Some(Next::Now("shop".to_owned())));
+
+        let (vars,_) = ev.next().unwrap();
+        assert_eq!(vars[0], "browse".into());
+
+        let (vars,_) = ev.next().unwrap();
+        assert_eq!(vars, vec![10. .into(), "Io".into()]);
+    }
+
+    // inline conditions are named after their source
+    match env.src["root"].src[0] {
+        Src::Logic(ref name, Logic::Expr(_)) => {
+            assert_eq!(name, "(player.gold > 10 and !player.cursed)");
+        },
+        _ => panic!("unknown source found")
+    }
+}
+
+#[test]
+fn call_args_return() {
+    let src = "root\n
+    @player.gold 10\n
+    next:call price_of \"sword\" 2\n
+    emit price_of qty\n
+    next:call price_of \"shield\" player.gold\n
+    emit price_of\n
+;\n
+price_of item qty\n
+    @cost 0\n
+    @cost + qty\n
+    @cost * 5\n
+    emit \"`item costs\"\n
+    return cost\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    assert!(env.validate().is_empty());
+
+    let mut ev = Evaluator::new(&mut env);
+    let (_,next) = ev.next().unwrap();
+    assert_eq!(next, Some(Next::Call("price_of".to_owned(),
+                                     vec!["sword".into(), 2.0 .into()])));
+
+    let (vars,_) = ev.next().unwrap();
+    assert_eq!(vars[0], "sword costs".into());
+
+    let (_,next) = ev.next().unwrap();
+    assert_eq!(next, Some(Next::Return(Some(Var::Sym("cost".to_owned())))));
+
+    // locals of the called node are not visible to the caller
+    let (vars,_) = ev.next().unwrap();
+    assert_eq!(vars, vec![10.0 .into(), Var::Sym("qty".to_owned())]);
+
+    let _ = ev.next(); // call
+    let (vars,_) = ev.next().unwrap();
+    assert_eq!(vars[0], "shield costs".into());
+    
+    let _ = ev.next(); // return
+    let (vars,_) = ev.next().unwrap();
+    assert_eq!(vars[0], 50.0 .into());
+}
+
+#[test]
+fn runaway_eval() {
+    let src = "root\n
+    next:now a\n
+;\n
+a\n
+    @global.count + 1\n
+    next:now b\n
+;\n
+b\n
+    next:now a\n
+;\n
+def global\n
+    count 0\n
+;";
+
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+    ev.set_max_steps(50);
+
+    while ev.next().is_some() {}
+    assert_eq!(ev.error(), Some(&EvalError::Runaway {
+        steps: 50,
+        nodes: vec!["root".to_owned(), "a".to_owned(), "b".to_owned()],
+    }));
+
+    // evaluation can resume with a fresh budget
+    assert!(ev.try_next().unwrap().is_some());
+}
+
+#[test]
+fn silent_eval_no_overflow() {
+    // many silent statements used to recurse once each
+    let mut src = "root\n".to_owned();
+    for i in 0..5000 {
+        src.push_str(&format!("    @global.n{} {}\n", i, i));
+    }
+    src.push_str("    emit global.n4999\n;");
+
+    let mut env = Parser::parse_blocks(&src).expect("ERROR: Unable to parse source").into_env();
+    let mut ev = Evaluator::new(&mut env);
+    let (vars,_) = ev.next().unwrap();
+    assert_eq!(vars[0], 4999.0 .into());
+}
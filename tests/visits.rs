@@ -0,0 +1,98 @@
+extern crate lichen;
+
+use lichen::parse::Parser;
+use lichen::eval::Evaluator;
+use lichen::var::Var;
+use lichen::lint::Warning;
+
+const SRC: &str = "root\n
+    next:call intro\n
+    next:call intro\n
+    next:call intro\n
+    next:call intro\n
+;\n
+intro\n
+    if !seen \"Hello, stranger\"\n
+    or \"Welcome back\"\n
+    if (intro.visits > 2) \"Regular\"\n
+    if visited:shop \"Bought anything?\"\n
+    next:call shop\n
+;\n
+shop\n
+    emit intro.visits\n
+;";
+
+fn lines (src: &str) -> Vec<Var> {
+    let mut env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    let ev = Evaluator::new(&mut env);
+    ev.flat_map(|(vars, _)| vars).collect()
+}
+
+#[test]
+fn visit_counts() {
+    let mut env = Parser::parse_blocks(SRC).expect("ERROR: Unable to parse source").into_env();
+    assert_eq!(env.visits("intro"), 0);
+
+    {
+        let ev = Evaluator::new(&mut env);
+        for _ in ev {}
+    }
+
+    assert_eq!(env.visits("root"), 1);
+    assert_eq!(env.visits("intro"), 4);
+    assert_eq!(env.visits("shop"), 4);
+    assert_eq!(env.visits("missing"), 0);
+    assert!(env.validate().is_empty());
+}
+
+#[test]
+fn visit_logic() {
+    let vars = lines(SRC);
+    assert_eq!(vars, vec!["Hello, stranger".into(), Var::Int(1),
+                          "Welcome back".into(), "Bought anything?".into(), Var::Int(2),
+                          "Welcome back".into(), "Regular".into(), "Bought anything?".into(), Var::Int(3),
+                          "Welcome back".into(), "Regular".into(), "Bought anything?".into(), Var::Int(4)]);
+}
+
+#[test]
+fn visit_def_precedence() {
+    let src = "root\n
+    emit intro.visits\n
+    next:now intro\n
+;\n
+intro\n
+    emit \"hi\"\n
+;\n
+def intro\n
+    visits \"from def\"\n
+;";
+
+    assert_eq!(lines(src), vec!["from def".into(), "hi".into()]);
+}
+
+#[test]
+fn visit_emitted_from_if() {
+    let src = "root\n
+    if g.t visited:root \"is visited\"\n
+    or visited:other \"not visited\"\n
+;\n
+def g\n
+    t true\n
+;";
+
+    assert_eq!(lines(src), vec![Var::Bool(true), "is visited".into()]);
+}
+
+#[test]
+fn visit_unknown_node() {
+    let src = "root\n
+    if visited:nowhere \"never\"\n
+    emit \"done\"\n
+;";
+
+    assert_eq!(lines(src), vec!["done".into()]);
+
+    let env = Parser::parse_blocks(src).expect("ERROR: Unable to parse source").into_env();
+    assert_eq!(env.validate(), vec![Warning::DanglingNode { block: "root".to_owned(),
+                                                            node: "nowhere".to_owned() }]);
+}